@@ -0,0 +1,198 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use drax::prelude::Uuid;
+
+// Who issued an administrative action.
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditSource {
+    Console,
+    Player { uuid: Uuid, name: String },
+    Remote { address: String },
+    // The startup script or a scheduled job.
+    Script { name: String },
+    // `--export`, `--import` or `--delete` run from the command line.
+    Tool,
+}
+
+impl std::fmt::Display for AuditSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditSource::Console => write!(f, "console"),
+            AuditSource::Player { name, .. } => write!(f, "{}", name),
+            AuditSource::Remote { address } => write!(f, "remote {}", address),
+            AuditSource::Script { name } => write!(f, "script {}", name),
+            AuditSource::Tool => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    // RFC 3339, UTC.
+    pub at: String,
+    pub source: AuditSource,
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_uuid: Option<Uuid>,
+    pub details: String,
+}
+
+impl AuditEntry {
+    pub fn new<S: Into<String>>(source: &AuditSource, action: &str, details: S) -> Self {
+        Self {
+            at: Utc::now().to_rfc3339(),
+            source: source.clone(),
+            action: action.to_string(),
+            target: None,
+            target_uuid: None,
+            details: details.into(),
+        }
+    }
+
+    pub fn target<S: Into<String>>(mut self, name: S, uuid: Option<Uuid>) -> Self {
+        self.target = Some(name.into());
+        self.target_uuid = uuid;
+        self
+    }
+
+    fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.at)
+            .ok()
+            .map(|at| at.with_timezone(&Utc))
+    }
+
+    fn involves(&self, player: &str) -> bool {
+        let uuid = Uuid::parse_str(player).ok();
+        let target = self
+            .target
+            .as_deref()
+            .map_or(false, |target| target.eq_ignore_ascii_case(player))
+            || (uuid.is_some() && self.target_uuid == uuid);
+        let actor = match &self.source {
+            AuditSource::Player { uuid: actor, name } => {
+                name.eq_ignore_ascii_case(player) || Some(*actor) == uuid
+            }
+            _ => false,
+        };
+        target || actor
+    }
+}
+
+impl std::fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}] {}", self.at, self.source, self.action)?;
+        if let Some(target) = &self.target {
+            write!(f, " {}", target)?;
+        }
+        write!(f, ": {}", self.details)
+    }
+}
+
+static AUDIT_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn audit_path() -> PathBuf {
+    crate::config::get().data_dir.join("audit.log")
+}
+
+// One JSON object per line, only ever appended to. A failed write is logged but never blocks the
+// action being audited.
+pub fn record(entry: AuditEntry) {
+    log::info!(target: "audit", "{}", entry);
+    let line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(err) => {
+            log::error!("Failed to encode audit entry: {}", err);
+            return;
+        }
+    };
+    let mut file = AUDIT_FILE.lock().unwrap();
+    if file.is_none() {
+        match OpenOptions::new().create(true).append(true).open(audit_path()) {
+            Ok(opened) => *file = Some(opened),
+            Err(err) => {
+                log::error!("Failed to open {}: {}", audit_path().display(), err);
+                return;
+            }
+        }
+    }
+    if let Some(opened) = file.as_mut() {
+        if let Err(err) = writeln!(opened, "{}", line).and_then(|_| opened.sync_data()) {
+            log::error!("Failed to write audit entry: {}", err);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AuditQuery {
+    pub player: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: usize,
+}
+
+// Accepts a relative age (`30m`, `12h`, `7d`), a date (`2023-01-31`) or an RFC 3339 timestamp.
+pub fn parse_time(input: &str) -> Option<DateTime<Utc>> {
+    if let Some(age) = parse_duration(input) {
+        return Some(Utc::now() - age);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?));
+    }
+    DateTime::parse_from_rfc3339(input)
+        .ok()
+        .map(|at| at.with_timezone(&Utc))
+}
+
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let unit = input.chars().last()?;
+    let amount = input[..input.len() - unit.len_utf8()].parse::<i64>().ok()?;
+    match unit {
+        's' => Some(Duration::seconds(amount)),
+        'm' => Some(Duration::minutes(amount)),
+        'h' => Some(Duration::hours(amount)),
+        'd' => Some(Duration::days(amount)),
+        _ => None,
+    }
+}
+
+// Returns the newest `limit` matching entries, oldest first.
+pub fn query(query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+    let path = audit_path();
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let mut matches = vec![];
+    for line in BufReader::new(File::open(&path)?).lines() {
+        let line = line?;
+        let entry: AuditEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if let Some(player) = &query.player {
+            if !entry.involves(player) {
+                continue;
+            }
+        }
+        if query.since.is_some() || query.until.is_some() {
+            let at = match entry.time() {
+                Some(at) => at,
+                None => continue,
+            };
+            if query.since.map_or(false, |since| at < since)
+                || query.until.map_or(false, |until| at > until)
+            {
+                continue;
+            }
+        }
+        matches.push(entry);
+    }
+    let skip = matches.len().saturating_sub(query.limit);
+    Ok(matches.split_off(skip))
+}
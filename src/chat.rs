@@ -13,6 +13,9 @@ use shovel::tick::{AwaitingEntity, CaptureAwaitingEntity, EntityFactory};
 use shovel::PacketSend;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
+use crate::audit::AuditSource;
+use crate::commands::CommandRequest;
+use crate::metrics::METRICS;
 use crate::ranks::Rank;
 
 pub enum ChatHandlerPacket {
@@ -20,6 +23,8 @@ pub enum ChatHandlerPacket {
     BroadcastConditionalMessage(Chat, fn(&ChatHandlerEntity) -> bool),
     NewClient(ChatHandlerEntityStub),
     UpdateRank(Uuid, Rank),
+    // Shown only to the given player.
+    DirectMessage(Uuid, Chat),
 }
 
 pub struct ChatHandlerEntityStub {
@@ -39,6 +44,7 @@ pub struct ChatHandlerEntity {
     init_ack: Option<tokio::sync::oneshot::Sender<()>>,
     pending_messages: VecDeque<String>,
     active: bool,
+    commands: UnboundedSender<CommandRequest>,
 }
 
 impl ChatHandlerEntity {
@@ -58,6 +64,47 @@ impl ChatHandlerEntity {
         self.rank.format_name(self.profile.name.clone())
     }
 
+    fn send_system_message(&self, content: Chat) {
+        let _ = self
+            .write_clone
+            .send(Arc::new(ClientboundPlayRegistry::SystemChat {
+                content,
+                overlay: false,
+            }));
+    }
+
+    fn send_command_tree(&self) {
+        let packet = crate::commands::brigadier::commands_packet(self.rank);
+        let _ = self.write_clone.send(Arc::new(packet));
+    }
+
+    // Commands run on the console task; the reply comes back whenever the command finishes.
+    fn run_command(&self, line: String) {
+        let (reply, output) = tokio::sync::oneshot::channel();
+        let request = CommandRequest {
+            line,
+            source: AuditSource::Player {
+                uuid: self.profile.id,
+                name: self.profile.name.clone(),
+            },
+            rank: self.rank,
+            reply: Some(reply),
+        };
+        if self.commands.send(request).is_err() {
+            self.send_system_message(msg!("Commands are unavailable right now.", "red").into());
+            return;
+        }
+        let write_clone = self.write_clone.clone();
+        tokio::spawn(async move {
+            for line in output.await.unwrap_or_default() {
+                let _ = write_clone.send(Arc::new(ClientboundPlayRegistry::SystemChat {
+                    content: msg!(line, "gray").into(),
+                    overlay: false,
+                }));
+            }
+        });
+    }
+
     fn style_chat_content(&self, content: String) -> Chat {
         let display_name = self.display_name();
         combine!(display_name, msg!(" ").into(), msg!(content).into()).into()
@@ -79,15 +126,14 @@ impl AwaitingEntity for ChatHandlerEntity {
                 }
                 Some(packet) => match packet {
                     ServerboundPlayRegistry::Chat { message, .. } => {
-                        if message.eq("stop")
-                            && self.profile.name.eq_ignore_ascii_case("DockerContainer")
-                        {
-                            std::process::exit(0)
-                        }
                         self.pending_messages.push_back(message);
                         ready = true;
                         true
                     }
+                    ServerboundPlayRegistry::ChatCommand { command, .. } => {
+                        self.run_command(command);
+                        true
+                    }
                     ServerboundPlayRegistry::ChatSessionUpdate { .. } => true,
                     packet => {
                         if let Err(_) = self.packet_send.send(packet) {
@@ -118,6 +164,7 @@ pub struct TamedChatHandler<'a> {
     new_client_queue: &'a mut VecDeque<ChatHandlerEntityStub>,
     new_messages: &'a mut VecDeque<(Chat, fn(&ChatHandlerEntity) -> bool)>,
     update_rank_reqs: &'a mut Vec<(Uuid, Rank)>,
+    direct_messages: &'a mut Vec<(Uuid, Chat)>,
 }
 
 impl<'a> AwaitingEntity for TamedChatHandler<'a> {
@@ -145,6 +192,10 @@ impl<'a> AwaitingEntity for TamedChatHandler<'a> {
                     needs_state_tick = true;
                     self.update_rank_reqs.push((id, rank));
                 }
+                ChatHandlerPacket::DirectMessage(id, message) => {
+                    needs_state_tick = true;
+                    self.direct_messages.push((id, message));
+                }
             }
         }
         Ok(needs_state_tick)
@@ -157,6 +208,8 @@ pub struct ChatHandler {
     new_client_queue: VecDeque<ChatHandlerEntityStub>,
     new_messages: VecDeque<(Chat, fn(&ChatHandlerEntity) -> bool)>,
     update_rank_reqs: Vec<(Uuid, Rank)>,
+    direct_messages: Vec<(Uuid, Chat)>,
+    commands: UnboundedSender<CommandRequest>,
 }
 
 struct InnerBroadcastPacket {
@@ -164,6 +217,32 @@ struct InnerBroadcastPacket {
     predicate: fn(&ChatHandlerEntity) -> bool,
 }
 
+const NAMED_COLORS: [&'static str; 16] = [
+    "black",
+    "dark_blue",
+    "dark_green",
+    "dark_aqua",
+    "dark_red",
+    "dark_purple",
+    "gold",
+    "gray",
+    "dark_gray",
+    "blue",
+    "green",
+    "aqua",
+    "red",
+    "light_purple",
+    "yellow",
+    "white",
+];
+
+pub fn is_valid_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => NAMED_COLORS.contains(&color),
+    }
+}
+
 fn default_bit_set() -> BitSet {
     let mut bit_set = BitSet::value_of(vec![]).unwrap();
     bit_set.set(0).unwrap();
@@ -212,15 +291,18 @@ impl ChatHandler {
                     init_ack: Some(client.init_ack),
                     pending_messages: Default::default(),
                     active: true,
+                    commands: self.commands.clone(),
                 };
+                entity.send_command_tree();
                 new_entries.push(entity.entry());
                 self.entities.insert(entity.profile.id.clone(), entity);
             }
 
             let mut updated_ranks = vec![];
-            for (id, rank) in &self.update_rank_reqs {
-                if let Some(entity) = self.entities.get_mut(id) {
-                    entity.rank = *rank;
+            for (id, rank) in self.update_rank_reqs.drain(..) {
+                if let Some(entity) = self.entities.get_mut(&id) {
+                    entity.rank = rank;
+                    entity.send_command_tree();
                     updated_ranks.push(entity.entry());
                 }
             }
@@ -265,10 +347,16 @@ impl ChatHandler {
             for id in &clients_to_remove {
                 self.entities.remove(id);
             }
+            for (id, message) in self.direct_messages.drain(..) {
+                if let Some(client) = self.entities.get(&id) {
+                    client.send_system_message(message);
+                }
+            }
             let mass_remove = Arc::new(ClientboundPlayRegistry::PlayerInfoRemove {
                 profile_ids: clients_to_remove,
             });
             while let Some((message, predicate)) = self.new_messages.pop_front() {
+                METRICS.chat_broadcasts.inc();
                 broadcast_packets.push(InnerBroadcastPacket {
                     packet: Arc::new(ClientboundPlayRegistry::SystemChat {
                         content: message,
@@ -315,13 +403,16 @@ impl EntityFactory for ChatHandler {
             new_client_queue: &mut self.new_client_queue,
             new_messages: &mut self.new_messages,
             update_rank_reqs: &mut self.update_rank_reqs,
+            direct_messages: &mut self.direct_messages,
         };
         let entities = self.entities.values_mut().collect::<Vec<_>>();
         (tamed, entities)
     }
 }
 
-pub fn create_global_chat_handle() -> UnboundedSender<ChatHandlerPacket> {
+pub fn create_global_chat_handle(
+    commands: UnboundedSender<CommandRequest>,
+) -> UnboundedSender<ChatHandlerPacket> {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     let mut chat_handler = ChatHandler {
         packet_recv: rx,
@@ -329,6 +420,8 @@ pub fn create_global_chat_handle() -> UnboundedSender<ChatHandlerPacket> {
         new_client_queue: Default::default(),
         new_messages: Default::default(),
         update_rank_reqs: Default::default(),
+        direct_messages: Default::default(),
+        commands,
     };
     tokio::spawn(async move { chat_handler.execute_handler_loop().await });
     tx
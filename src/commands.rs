@@ -0,0 +1,555 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use drax::prelude::Uuid;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+
+use crate::audit::AuditSource;
+use crate::console::{ConsoleHandle, ConsolePacket, ConsoleState};
+use crate::game::blocks::GLOBAL_BLOCK_REGISTRY;
+use crate::ranks::Rank;
+
+mod admin;
+pub mod brigadier;
+mod game;
+mod players;
+mod server;
+
+const RANKS: &'static [&'static str] = &["default", "staff", "owner"];
+
+// Every command the server knows, in help order. Console and remote sources run as `Owner`.
+pub static COMMANDS: &'static [Command] = &[
+    server::HELP,
+    game::SPAWN,
+    game::STATS,
+    game::MENU,
+    game::MSG,
+    server::STOP,
+    server::TPS,
+    server::RELOAD,
+    server::BROADCAST,
+    server::SAVE_ALL,
+    server::JOBS,
+    players::LIST,
+    players::INSPECT,
+    players::KICK,
+    admin::RANK,
+    admin::AUDIT,
+    admin::CONVERT,
+    admin::EXPORT,
+    admin::IMPORT,
+    admin::DELETE,
+    admin::SNAPSHOT,
+    admin::SNAPSHOTS,
+    admin::ROLLBACK,
+];
+
+pub enum ArgKind {
+    // A player name or uuid; resolving it (online or offline) is up to the handler.
+    Player,
+    Rank,
+    Integer { min: i64, max: i64 },
+    // `30s`, `5m`, `2h`, `1d`.
+    Duration,
+    BlockOrdinal,
+    Word,
+    Choice(&'static [&'static str]),
+    // Consumes the rest of the line.
+    Text,
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub required: bool,
+}
+
+pub const fn required(name: &'static str, kind: ArgKind) -> Arg {
+    Arg {
+        name,
+        kind,
+        required: true,
+    }
+}
+
+pub const fn optional(name: &'static str, kind: ArgKind) -> Arg {
+    Arg {
+        name,
+        kind,
+        required: false,
+    }
+}
+
+pub type Handler = fn(&mut CommandContext, &Args) -> CommandResult;
+
+pub struct Command {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub permission: Rank,
+    pub args: &'static [Arg],
+    // `false` keeps the arguments out of the log, for things like private messages.
+    pub log_args: bool,
+    pub subcommands: &'static [Command],
+    // `None` for pure groups, which only print their sub-commands.
+    pub handler: Option<Handler>,
+}
+
+impl Command {
+    fn usage(&self, path: &str) -> String {
+        let mut usage = path.to_string();
+        for arg in self.args {
+            let name = match arg.kind {
+                ArgKind::Rank => RANKS.join("|"),
+                ArgKind::Choice(choices) => choices.join("|"),
+                _ => arg.name.to_string(),
+            };
+            if arg.required {
+                usage.push_str(&format!(" <{}>", name));
+            } else {
+                usage.push_str(&format!(" [{}]", name));
+            }
+        }
+        if !self.subcommands.is_empty() && self.handler.is_none() {
+            let names = self
+                .subcommands
+                .iter()
+                .map(|command| command.name)
+                .collect::<Vec<_>>();
+            usage.push_str(&format!(" <{}>", names.join("|")));
+        }
+        usage
+    }
+
+    pub fn can_run(&self, rank: Rank) -> bool {
+        rank >= self.permission
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(name))
+}
+
+// The command line as it may appear in the log.
+pub fn log_line(line: &str) -> String {
+    let name = line.split_whitespace().next().unwrap_or_default();
+    match find(name) {
+        Some(command) if !command.log_args => format!("{} [arguments hidden]", command.name),
+        _ => line.to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Player(String),
+    Rank(Rank),
+    Integer(i64),
+    Duration(Duration),
+    BlockOrdinal(usize),
+    Word(String),
+    Text(String),
+}
+
+// Required arguments are always present once dispatch reaches the handler.
+#[derive(Default)]
+pub struct Args {
+    values: HashMap<&'static str, Value>,
+}
+
+impl Args {
+    pub fn player(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(Value::Player(player)) => Some(player),
+            _ => None,
+        }
+    }
+
+    pub fn rank(&self, name: &str) -> Option<Rank> {
+        match self.values.get(name) {
+            Some(Value::Rank(rank)) => Some(*rank),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.values.get(name) {
+            Some(Value::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self, name: &str) -> Option<Duration> {
+        match self.values.get(name) {
+            Some(Value::Duration(duration)) => Some(*duration),
+            _ => None,
+        }
+    }
+
+    pub fn block_ordinal(&self, name: &str) -> Option<usize> {
+        match self.values.get(name) {
+            Some(Value::BlockOrdinal(ordinal)) => Some(*ordinal),
+            _ => None,
+        }
+    }
+
+    // Word, choice and text arguments.
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(Value::Word(text)) | Some(Value::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Unknown(String),
+    Permission,
+    Usage(String),
+    Failed(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Unknown(name) => {
+                write!(f, "Unknown command `{}`, try `help`.", name)
+            }
+            CommandError::Permission => write!(f, "You don't have permission to do that."),
+            CommandError::Usage(usage) => write!(f, "{}", usage),
+            CommandError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl From<anyhow::Error> for CommandError {
+    fn from(err: anyhow::Error) -> Self {
+        CommandError::Failed(format!("{:#}", err))
+    }
+}
+
+pub type CommandResult = Result<(), CommandError>;
+
+pub type CommandFuture = Pin<Box<dyn Future<Output = Vec<String>> + Send>>;
+
+// A command line from any source; the console task executes it and answers on `reply` (or logs
+// the output when there is no one to answer).
+pub struct CommandRequest {
+    pub line: String,
+    pub source: AuditSource,
+    pub rank: Rank,
+    pub reply: Option<oneshot::Sender<Vec<String>>>,
+}
+
+impl CommandRequest {
+    pub fn console<S: Into<String>>(line: S) -> Self {
+        Self {
+            line: line.into(),
+            source: AuditSource::Console,
+            rank: Rank::Owner,
+            reply: None,
+        }
+    }
+}
+
+pub struct CommandContext<'a> {
+    pub source: AuditSource,
+    pub rank: Rank,
+    pub state: &'a ConsoleState,
+    output: Vec<String>,
+    deferred: Option<CommandFuture>,
+}
+
+impl<'a> CommandContext<'a> {
+    pub fn new(source: AuditSource, rank: Rank, state: &'a ConsoleState) -> Self {
+        Self {
+            source,
+            rank,
+            state,
+            output: vec![],
+            deferred: None,
+        }
+    }
+
+    pub fn reply<S: Into<String>>(&mut self, line: S) {
+        self.output.push(line.into());
+    }
+
+    // For work that has to wait (kicks, flushes, disk I/O); its lines follow the immediate ones.
+    pub fn defer<F: Future<Output = Vec<String>> + Send + 'static>(&mut self, future: F) {
+        self.deferred = Some(Box::pin(future));
+    }
+
+    // `defer` for work that can fail; the error is reported the way a handler's would be.
+    pub fn defer_try<F>(&mut self, future: F)
+    where
+        F: Future<Output = Result<Vec<String>, CommandError>> + Send + 'static,
+    {
+        self.defer(async move { future.await.unwrap_or_else(|err| vec![err.to_string()]) });
+    }
+
+    pub fn finish(self) -> (Vec<String>, Option<CommandFuture>) {
+        (self.output, self.deferred)
+    }
+}
+
+// A player argument resolved in deferred work: online players by name first, then the name
+// index (which also accepts uuids) on a blocking thread. Keeps the sessions that were online when
+// the command ran, since deferred work can't reach the console state.
+struct PlayerLookup {
+    player: String,
+    online: Vec<ConsoleHandle>,
+}
+
+impl PlayerLookup {
+    fn new(ctx: &CommandContext, player: &str) -> Self {
+        Self {
+            player: player.to_string(),
+            online: ctx.state.online().cloned().collect(),
+        }
+    }
+
+    async fn resolve(&self) -> Result<Uuid, CommandError> {
+        let online = self
+            .online
+            .iter()
+            .find(|(profile, _)| profile.name.eq_ignore_ascii_case(&self.player));
+        if let Some((profile, _)) = online {
+            return Ok(profile.id);
+        }
+        let player = self.player.clone();
+        tokio::task::spawn_blocking(move || crate::db::names::resolve(&player))
+            .await
+            .map_err(|err| CommandError::Failed(format!("Player lookup panicked: {}", err)))??
+            .ok_or_else(|| CommandError::Failed(format!("Could not find player {}.", self.player)))
+    }
+
+    fn session(&self, uuid: Uuid) -> Option<&ConsoleHandle> {
+        self.online
+            .iter()
+            .find(|(profile, handle)| profile.id == uuid && !handle.is_closed())
+    }
+}
+
+// An online player by name or uuid; needs no storage, so it's fine on the console task.
+fn online_player<'a>(ctx: &CommandContext<'a>, player: &str) -> Option<&'a ConsoleHandle> {
+    let state = ctx.state;
+    if let Ok(uuid) = Uuid::parse_str(player) {
+        return state.online().find(|(profile, _)| profile.id == uuid);
+    }
+    state.find_online_by_name(player)
+}
+
+// For commands that act on the player running them.
+fn invoking_player(
+    ctx: &CommandContext,
+) -> Result<(Uuid, UnboundedSender<ConsolePacket>), CommandError> {
+    let uuid = match &ctx.source {
+        AuditSource::Player { uuid, .. } => *uuid,
+        _ => {
+            return Err(CommandError::Failed(
+                "Only players can use this command.".to_string(),
+            ))
+        }
+    };
+    let handle = ctx.state.find_online(uuid).ok_or_else(|| {
+        CommandError::Failed("Your session hasn't started yet, try again in a moment.".to_string())
+    })?;
+    Ok((uuid, handle))
+}
+
+fn parse_value(kind: &ArgKind, token: &str) -> Result<Value, String> {
+    match kind {
+        ArgKind::Player => Ok(Value::Player(token.to_string())),
+        ArgKind::Rank => match token.to_ascii_lowercase().as_str() {
+            "default" => Ok(Value::Rank(Rank::Default)),
+            "staff" => Ok(Value::Rank(Rank::Staff)),
+            "owner" => Ok(Value::Rank(Rank::Owner)),
+            _ => Err(format!("expected one of {}", RANKS.join(", "))),
+        },
+        ArgKind::Integer { min, max } => match token.parse::<i64>() {
+            Ok(value) if (*min..=*max).contains(&value) => Ok(Value::Integer(value)),
+            Ok(_) => Err(format!("expected a number from {} to {}", min, max)),
+            Err(_) => Err("expected a number".to_string()),
+        },
+        ArgKind::Duration => crate::audit::parse_duration(token)
+            .and_then(|duration| duration.to_std().ok())
+            .map(Value::Duration)
+            .ok_or_else(|| "expected a duration like 30s, 5m, 2h or 1d".to_string()),
+        ArgKind::BlockOrdinal => match token.parse::<usize>() {
+            Ok(ordinal) if GLOBAL_BLOCK_REGISTRY.current().search_by_ordinal(ordinal).is_some() => {
+                Ok(Value::BlockOrdinal(ordinal))
+            }
+            Ok(_) => Err("no block has that ordinal".to_string()),
+            Err(_) => Err("expected a block ordinal".to_string()),
+        },
+        ArgKind::Word => Ok(Value::Word(token.to_string())),
+        ArgKind::Choice(choices) => choices
+            .iter()
+            .find(|choice| choice.eq_ignore_ascii_case(token))
+            .map(|choice| Value::Word(choice.to_string()))
+            .ok_or_else(|| format!("expected one of {}", choices.join(", "))),
+        ArgKind::Text => Ok(Value::Text(token.to_string())),
+    }
+}
+
+fn parse_args(command: &Command, path: &str, tokens: &[&str]) -> Result<Args, CommandError> {
+    let usage = || CommandError::Usage(format!("Usage: {}", command.usage(path)));
+    let mut args = Args::default();
+    let mut remaining = tokens;
+    for arg in command.args {
+        if let ArgKind::Text = arg.kind {
+            if !remaining.is_empty() {
+                let text = remaining.join(" ");
+                args.values.insert(arg.name, Value::Text(text));
+            } else if arg.required {
+                return Err(usage());
+            }
+            remaining = &[];
+            continue;
+        }
+        let token = match remaining.split_first() {
+            Some((token, rest)) => {
+                remaining = rest;
+                token
+            }
+            None if arg.required => return Err(usage()),
+            None => break,
+        };
+        let value = parse_value(&arg.kind, token).map_err(|reason| {
+            CommandError::Usage(format!(
+                "Invalid {} `{}`: {}. Usage: {}",
+                arg.name,
+                token,
+                reason,
+                command.usage(path)
+            ))
+        })?;
+        args.values.insert(arg.name, value);
+    }
+    if !remaining.is_empty() {
+        return Err(usage());
+    }
+    Ok(args)
+}
+
+fn run(ctx: &mut CommandContext, line: &str) -> CommandResult {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let (name, mut rest) = match tokens.split_first() {
+        Some((name, rest)) => (*name, rest),
+        None => return Ok(()),
+    };
+    let mut command = find(name).ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+    let mut path = command.name.to_string();
+    loop {
+        if !command.can_run(ctx.rank) {
+            return Err(CommandError::Permission);
+        }
+        let subcommand = rest.first().and_then(|next| {
+            command
+                .subcommands
+                .iter()
+                .find(|subcommand| subcommand.name.eq_ignore_ascii_case(next))
+        });
+        match subcommand {
+            Some(subcommand) => {
+                command = subcommand;
+                path = format!("{} {}", path, command.name);
+                rest = &rest[1..];
+            }
+            None => break,
+        }
+    }
+    let handler = command
+        .handler
+        .ok_or_else(|| CommandError::Usage(format!("Usage: {}", command.usage(&path))))?;
+    let args = parse_args(command, &path, rest)?;
+    handler(ctx, &args)
+}
+
+pub fn dispatch(ctx: &mut CommandContext, line: &str) {
+    if let Err(err) = run(ctx, line) {
+        ctx.reply(err.to_string());
+    }
+}
+
+// Tab completion candidates for `word`, given the complete words typed before it.
+pub fn complete(preceding: &str, word: &str, players: &[String]) -> Vec<String> {
+    let matches = |candidate: &str| {
+        candidate
+            .get(..word.len())
+            .map_or(false, |prefix| prefix.eq_ignore_ascii_case(word))
+    };
+    let tokens = preceding.split_whitespace().collect::<Vec<_>>();
+    let (name, mut rest) = match tokens.split_first() {
+        Some((name, rest)) => (*name, rest),
+        None => {
+            return COMMANDS
+                .iter()
+                .map(|command| command.name.to_string())
+                .filter(|name| matches(name))
+                .collect();
+        }
+    };
+    let mut command = match find(name) {
+        Some(command) => command,
+        None => return vec![],
+    };
+    while let Some(subcommand) = rest.first().and_then(|next| {
+        command
+            .subcommands
+            .iter()
+            .find(|subcommand| subcommand.name.eq_ignore_ascii_case(next))
+    }) {
+        command = subcommand;
+        rest = &rest[1..];
+    }
+
+    let mut candidates = vec![];
+    if rest.is_empty() {
+        candidates.extend(command.subcommands.iter().map(|sub| sub.name.to_string()));
+    }
+    match command.args.get(rest.len()).map(|arg| &arg.kind) {
+        Some(ArgKind::Player) => candidates.extend(players.iter().cloned()),
+        Some(ArgKind::Rank) => candidates.extend(RANKS.iter().map(|rank| rank.to_string())),
+        Some(ArgKind::Choice(choices)) => {
+            candidates.extend(choices.iter().map(|choice| choice.to_string()))
+        }
+        _ => {}
+    }
+    candidates.retain(|candidate| matches(candidate));
+    candidates
+}
+
+// Lines for `help`: every command the rank may run, or the details of one command.
+pub fn help_lines(rank: Rank, command: Option<&str>) -> Vec<String> {
+    let mut lines = vec![];
+    match command {
+        None => {
+            lines.push("Available commands:".to_string());
+            for command in COMMANDS.iter().filter(|command| command.can_run(rank)) {
+                lines.push(format!("{} - {}", command.usage(command.name), command.description));
+            }
+        }
+        Some(name) => match find(name).filter(|command| command.can_run(rank)) {
+            Some(command) => {
+                lines.push(format!("{} - {}", command.usage(command.name), command.description));
+                for subcommand in command.subcommands.iter().filter(|sub| sub.can_run(rank)) {
+                    let path = format!("{} {}", command.name, subcommand.name);
+                    lines.push(format!(
+                        "  {} - {}",
+                        subcommand.usage(&path),
+                        subcommand.description
+                    ));
+                }
+            }
+            None => lines.push(CommandError::Unknown(name.to_string()).to_string()),
+        },
+    }
+    lines
+}
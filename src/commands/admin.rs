@@ -0,0 +1,423 @@
+use std::path::{Component, PathBuf};
+
+use anyhow::Context;
+
+use crate::audit::{AuditEntry, AuditQuery, AuditSource};
+use crate::commands::{
+    online_player, optional, required, ArgKind, Args, Command, CommandContext, CommandError,
+    CommandResult, PlayerLookup,
+};
+use crate::config::SaveFormat;
+use crate::console::{kick_and_wait, ConsolePacket};
+use crate::db::bundle::{ConflictPolicy, PlayerBundle};
+use crate::db::{snapshots, DbHook};
+use crate::ranks::Rank;
+
+const FORMATS: &'static [&'static str] = &["json", "binary", "binary_compressed"];
+const POLICIES: &'static [&'static str] = &["skip", "overwrite", "fail"];
+
+pub const RANK: Command = Command {
+    name: "rank",
+    description: "set a player's rank, online or offline",
+    permission: Rank::Owner,
+    args: &[
+        required("player", ArgKind::Player),
+        required("rank", ArgKind::Rank),
+    ],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(rank),
+};
+
+pub const AUDIT: Command = Command {
+    name: "audit",
+    description: "query the audit log; filters are `player <p>`, `since <t>`, `until <t>` and \
+                  `limit <n>`, times an age (30m, 7d), a date or RFC 3339",
+    permission: Rank::Staff,
+    args: &[optional("filters", ArgKind::Text)],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(audit),
+};
+
+pub const CONVERT: Command = Command {
+    name: "convert",
+    description: "rewrite every player save in the given format",
+    permission: Rank::Owner,
+    args: &[required("format", ArgKind::Choice(FORMATS))],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(convert),
+};
+
+pub const EXPORT: Command = Command {
+    name: "export",
+    description: "export one or all players to a bundle file",
+    permission: Rank::Owner,
+    args: &[
+        required("file", ArgKind::Word),
+        optional("player", ArgKind::Player),
+    ],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(export),
+};
+
+pub const IMPORT: Command = Command {
+    name: "import",
+    description: "import a bundle file; existing players are skipped unless told otherwise",
+    permission: Rank::Owner,
+    args: &[
+        required("file", ArgKind::Word),
+        optional("on_conflict", ArgKind::Choice(POLICIES)),
+    ],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(import),
+};
+
+pub const DELETE: Command = Command {
+    name: "delete",
+    description: "permanently delete a player's data",
+    permission: Rank::Owner,
+    args: &[
+        required("player", ArgKind::Player),
+        optional("confirm", ArgKind::Choice(&["confirm"])),
+    ],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(delete),
+};
+
+pub const SNAPSHOT: Command = Command {
+    name: "snapshot",
+    description: "snapshot all player data now",
+    permission: Rank::Owner,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(snapshot),
+};
+
+pub const SNAPSHOTS: Command = Command {
+    name: "snapshots",
+    description: "list snapshots",
+    permission: Rank::Staff,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(list_snapshots),
+};
+
+pub const ROLLBACK: Command = Command {
+    name: "rollback",
+    description: "restore a player from a snapshot",
+    permission: Rank::Owner,
+    args: &[
+        required("player", ArgKind::Player),
+        required("snapshot", ArgKind::Word),
+    ],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(rollback),
+};
+
+async fn blocking<T, F>(work: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|err| anyhow::anyhow!("task panicked: {}", err))?
+}
+
+// The console and scripts may use any path. Players and remote sources are limited to relative
+// paths inside `data_dir`, so they can't read or overwrite arbitrary files.
+fn bundle_path(ctx: &CommandContext, file: &str) -> Result<PathBuf, CommandError> {
+    let path = PathBuf::from(file);
+    if matches!(ctx.source, AuditSource::Console | AuditSource::Script { .. }) {
+        return Ok(path);
+    }
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(CommandError::Failed(
+            "Only plain relative paths are allowed; they are resolved inside the data directory."
+                .to_string(),
+        ));
+    }
+    Ok(crate::config::get().data_dir.join(path))
+}
+
+fn rank(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let player = args.player("player").unwrap();
+    let rank = args.rank("rank").unwrap();
+    if let Some((profile, handle)) = online_player(ctx, player) {
+        let _ = handle.send(ConsolePacket::UpdateRank(rank));
+        crate::audit::record(
+            AuditEntry::new(&ctx.source, "rank", format!("set to {:?}", rank))
+                .target(profile.name.clone(), Some(profile.id)),
+        );
+        ctx.reply(format!("Updated {}'s rank!", profile.name));
+        return Ok(());
+    }
+
+    // Edits the stored record directly; only safe while the player has no session.
+    let lookup = PlayerLookup::new(ctx, player);
+    let saves = ctx.state.saves.clone();
+    let source = ctx.source.clone();
+    ctx.defer_try(async move {
+        let uuid = lookup.resolve().await?;
+        // A save queued on disconnect would otherwise land after this and undo it.
+        crate::db::flush(&saves).await;
+        let updated = blocking(move || {
+            let hook = DbHook::player(uuid);
+            let mut info = match hook.load()? {
+                Some(info) => info,
+                None => return Ok(None),
+            };
+            info.rank = rank;
+            hook.insert(&info)?;
+            Ok(Some(info.name))
+        })
+        .await
+        .context("Rank update failed")?;
+        let name = updated.ok_or_else(|| {
+            CommandError::Failed(format!("{} has no stored data.", lookup.player))
+        })?;
+        crate::audit::record(
+            AuditEntry::new(&source, "rank", format!("set to {:?} (offline)", rank))
+                .target(name.clone(), Some(uuid)),
+        );
+        Ok(vec![format!("Updated offline player {}'s rank!", name)])
+    });
+    Ok(())
+}
+
+fn audit(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let mut query = AuditQuery {
+        limit: 20,
+        ..Default::default()
+    };
+    let filters = args
+        .text("filters")
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    for pair in filters.chunks(2) {
+        let parsed = match pair {
+            ["player", player] => {
+                query.player = Some(player.to_string());
+                true
+            }
+            ["since", since] => crate::audit::parse_time(since)
+                .map(|since| query.since = Some(since))
+                .is_some(),
+            ["until", until] => crate::audit::parse_time(until)
+                .map(|until| query.until = Some(until))
+                .is_some(),
+            ["limit", limit] => match limit.parse() {
+                Ok(limit) if limit > 0 => {
+                    query.limit = limit;
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        if !parsed {
+            return Err(CommandError::Usage(
+                "Usage: audit [player <p>] [since <t>] [until <t>] [limit <n>]".to_string(),
+            ));
+        }
+    }
+    ctx.defer_try(async move {
+        let entries = blocking(move || crate::audit::query(&query)).await?;
+        if entries.is_empty() {
+            return Ok(vec!["No matching audit entries.".to_string()]);
+        }
+        Ok(entries.iter().map(|entry| entry.to_string()).collect())
+    });
+    Ok(())
+}
+
+fn convert(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let format = SaveFormat::parse(args.text("format").unwrap()).unwrap();
+    let configured = crate::config::get().save_format;
+    if format != configured {
+        ctx.reply(format!(
+            "save_format in the config is {:?}; online players will be saved in that format again.",
+            configured
+        ));
+    }
+    crate::audit::record(AuditEntry::new(
+        &ctx.source,
+        "convert",
+        format!("all player saves to {:?}", format),
+    ));
+    ctx.defer(async move {
+        let line = match blocking(move || crate::db::convert_players(format)).await {
+            Ok((converted, 0, 0)) => {
+                format!("Converted {} player save(s) to {:?}.", converted, format)
+            }
+            Ok((converted, skipped, 0)) => format!(
+                "Converted {} player save(s) to {:?}, skipped {} that disappeared.",
+                converted, format, skipped
+            ),
+            Ok((converted, skipped, failed)) => format!(
+                "Converted {} player save(s) to {:?}, skipped {}, {} failed (see the log).",
+                converted, format, skipped, failed
+            ),
+            Err(err) => format!("Conversion failed: {:#}", err),
+        };
+        vec![line]
+    });
+    Ok(())
+}
+
+fn export(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let file = args.text("file").unwrap().to_string();
+    let path = bundle_path(ctx, &file)?;
+    let lookup = args.player("player").map(|player| PlayerLookup::new(ctx, player));
+    let saves = ctx.state.saves.clone();
+    let source = ctx.source.clone();
+    ctx.defer_try(async move {
+        let player = match &lookup {
+            Some(lookup) => Some(lookup.resolve().await?),
+            None => None,
+        };
+        // Pick up anything online players have queued.
+        crate::db::flush(&saves).await;
+        let summary = blocking(move || crate::db::bundle::export(player, &path))
+            .await
+            .context("Export failed")?;
+        let details = summary.describe(&file);
+        crate::audit::record(AuditEntry::new(&source, "export", details.clone()));
+        Ok(vec![format!("Exported {}.", details)])
+    });
+    Ok(())
+}
+
+fn import(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let file = args.text("file").unwrap().to_string();
+    let path = bundle_path(ctx, &file)?;
+    let policy = args
+        .text("on_conflict")
+        .and_then(ConflictPolicy::parse)
+        .unwrap_or(ConflictPolicy::Skip);
+    let online = ctx.state.online().cloned().collect::<Vec<_>>();
+    let saves = ctx.state.saves.clone();
+    let source = ctx.source.clone();
+    ctx.defer_try(async move {
+        let bundle = blocking(move || PlayerBundle::read(&path)).await?;
+        let uuids = bundle.uuids();
+        let restoring = crate::db::begin_restore(uuids.clone());
+        // Overwritten players are kicked first, otherwise their session would save over the
+        // import.
+        if policy == ConflictPolicy::Overwrite {
+            for (_, handle) in online.iter().filter(|(profile, _)| uuids.contains(&profile.id)) {
+                kick_and_wait(handle.clone(), "Your player data is being replaced.", false).await;
+            }
+        }
+        crate::db::flush(&saves).await;
+        let imported = blocking(move || bundle.import(policy)).await;
+        drop(restoring);
+        let summary = imported.context("Import failed")?;
+        let details = summary.describe(&file);
+        crate::audit::record(AuditEntry::new(&source, "import", details.clone()));
+        Ok(vec![format!("Imported {}.", details)])
+    });
+    Ok(())
+}
+
+fn delete(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let player = args.player("player").unwrap().to_string();
+    if args.text("confirm").is_none() {
+        ctx.reply(format!(
+            "This permanently deletes {}'s data; run `delete {} confirm` to proceed.",
+            player, player
+        ));
+        return Ok(());
+    }
+    let lookup = PlayerLookup::new(ctx, &player);
+    let saves = ctx.state.saves.clone();
+    let source = ctx.source.clone();
+    ctx.defer_try(async move {
+        let uuid = lookup.resolve().await?;
+        let restoring = crate::db::begin_restore(vec![uuid]);
+        if let Some((_, handle)) = lookup.session(uuid) {
+            kick_and_wait(handle.clone(), "Your player data has been deleted.", false).await;
+        }
+        crate::db::flush(&saves).await;
+        let deleted = blocking(move || crate::db::bundle::delete_player(uuid)).await;
+        drop(restoring);
+        if !deleted.context("Delete failed")? {
+            return Ok(vec![format!("{} had no stored data.", player)]);
+        }
+        crate::audit::record(
+            AuditEntry::new(&source, "delete", "all stored data")
+                .target(player.clone(), Some(uuid)),
+        );
+        Ok(vec![format!("Deleted all stored data for {}.", player)])
+    });
+    Ok(())
+}
+
+fn snapshot(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    let saves = ctx.state.saves.clone();
+    let retention = crate::config::get().snapshots.retention;
+    ctx.defer(async move {
+        crate::db::flush(&saves).await;
+        match blocking(move || snapshots::take_snapshot(retention)).await {
+            Ok((id, count, 0)) => vec![format!("Snapshot {} taken ({} player(s)).", id, count)],
+            Ok((id, count, unreadable)) => vec![format!(
+                "Snapshot {} taken ({} player(s)); left out {} unreadable record(s), see the log.",
+                id, count, unreadable
+            )],
+            Err(err) => vec![format!("Snapshot failed: {:#}", err)],
+        }
+    });
+    Ok(())
+}
+
+fn list_snapshots(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    ctx.defer_try(async move {
+        let ids = blocking(snapshots::list).await?;
+        if ids.is_empty() {
+            return Ok(vec!["No snapshots yet.".to_string()]);
+        }
+        Ok(vec![format!("Snapshots (oldest first): {}", ids.join(", "))])
+    });
+    Ok(())
+}
+
+fn rollback(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let lookup = PlayerLookup::new(ctx, args.player("player").unwrap());
+    let snapshot = args.text("snapshot").unwrap().to_string();
+    let saves = ctx.state.saves.clone();
+    let source = ctx.source.clone();
+    ctx.defer_try(async move {
+        let uuid = lookup.resolve().await?;
+        // Check the snapshot before kicking anyone.
+        let check = snapshot.clone();
+        blocking(move || snapshots::find_player(&check, uuid)).await?;
+        let restoring = crate::db::begin_restore(vec![uuid]);
+        if let Some((_, handle)) = lookup.session(uuid) {
+            let reason = "Your progress is being rolled back, please reconnect.";
+            kick_and_wait(handle.clone(), reason, false).await;
+        }
+        crate::db::flush(&saves).await;
+        let restore = snapshot.clone();
+        let restored = blocking(move || snapshots::restore_player(&restore, uuid)).await;
+        drop(restoring);
+        let restored = restored.context("Rollback failed")?;
+        crate::audit::record(
+            AuditEntry::new(&source, "rollback", format!("to snapshot {}", snapshot))
+                .target(restored.name.clone(), Some(uuid)),
+        );
+        Ok(vec![format!("Rolled {} back to snapshot {}.", restored.name, snapshot)])
+    });
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use mcprotocol::clientbound::play::ClientboundPlayRegistry;
+use mcprotocol::common::play::{CommandNode, CommandNodeType, CommandParser};
+
+use crate::commands::{ArgKind, Command, COMMANDS, RANKS};
+use crate::ranks::Rank;
+
+// Brigadier's greedy string and word modes for `brigadier:string`.
+const STRING_WORD: i32 = 0;
+const STRING_GREEDY: i32 = 2;
+
+enum Parser {
+    Word,
+    Greedy,
+    Integer { min: i32, max: i32 },
+    Player,
+}
+
+enum Kind {
+    Root,
+    Literal(String),
+    Argument { name: String, parser: Parser },
+}
+
+struct Node {
+    kind: Kind,
+    executable: bool,
+    children: Vec<usize>,
+}
+
+// A flat node list in the shape the Commands packet wants, root at index 0.
+#[derive(Default)]
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn push(&mut self, parents: &[usize], kind: Kind, executable: bool) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            kind,
+            executable,
+            children: vec![],
+        });
+        for parent in parents {
+            self.nodes[*parent].children.push(index);
+        }
+        index
+    }
+
+    fn add_command(&mut self, parent: usize, command: &Command, rank: Rank) {
+        if !command.can_run(rank) {
+            return;
+        }
+        let runnable = command.handler.is_some();
+        let bare = runnable && command.args.iter().all(|arg| !arg.required);
+        let literal = self.push(&[parent], Kind::Literal(command.name.to_string()), bare);
+        for subcommand in command.subcommands {
+            self.add_command(literal, subcommand, rank);
+        }
+        if !runnable {
+            return;
+        }
+
+        // Choices become one literal per option; the next argument hangs off all of them.
+        let mut parents = vec![literal];
+        for (index, arg) in command.args.iter().enumerate() {
+            let executable = command.args[index + 1..].iter().all(|arg| !arg.required);
+            let kinds = match arg.kind {
+                ArgKind::Rank => literals(RANKS),
+                ArgKind::Choice(choices) => literals(choices),
+                ArgKind::Player => vec![argument(arg.name, Parser::Player)],
+                ArgKind::Integer { min, max } => vec![argument(
+                    arg.name,
+                    Parser::Integer {
+                        min: min.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+                        max: max.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+                    },
+                )],
+                ArgKind::Text => vec![argument(arg.name, Parser::Greedy)],
+                ArgKind::Duration | ArgKind::BlockOrdinal | ArgKind::Word => {
+                    vec![argument(arg.name, Parser::Word)]
+                }
+            };
+            parents = kinds
+                .into_iter()
+                .map(|kind| self.push(&parents, kind, executable))
+                .collect();
+        }
+    }
+}
+
+fn literals(names: &[&str]) -> Vec<Kind> {
+    names
+        .iter()
+        .map(|name| Kind::Literal(name.to_string()))
+        .collect()
+}
+
+fn argument(name: &str, parser: Parser) -> Kind {
+    Kind::Argument {
+        name: name.to_string(),
+        parser,
+    }
+}
+
+fn build(rank: Rank) -> Tree {
+    let mut tree = Tree::default();
+    tree.push(&[], Kind::Root, false);
+    for command in COMMANDS {
+        tree.add_command(0, command, rank);
+    }
+    tree
+}
+
+// The client tab-completes from this tree, so it only holds what `rank` may run.
+pub fn commands_packet(rank: Rank) -> ClientboundPlayRegistry {
+    let nodes = build(rank)
+        .nodes
+        .into_iter()
+        .map(|node| {
+            let children = node.children.into_iter().map(|child| child as i32).collect();
+            let node_type = match node.kind {
+                Kind::Root => CommandNodeType::Root,
+                Kind::Literal(name) => CommandNodeType::Literal { name },
+                Kind::Argument { name, parser } => CommandNodeType::Argument {
+                    name,
+                    parser: match parser {
+                        Parser::Word => CommandParser::String(STRING_WORD),
+                        Parser::Greedy => CommandParser::String(STRING_GREEDY),
+                        Parser::Integer { min, max } => CommandParser::Integer {
+                            min: Some(min),
+                            max: Some(max),
+                        },
+                        Parser::Player => CommandParser::GameProfile,
+                    },
+                    suggestions_type: None,
+                },
+            };
+            CommandNode {
+                node_type,
+                executable: node.executable,
+                children,
+                redirect_node: None,
+            }
+        })
+        .collect();
+    ClientboundPlayRegistry::Commands {
+        nodes,
+        root_index: 0,
+    }
+}
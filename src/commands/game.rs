@@ -0,0 +1,143 @@
+use mcprotocol::msg;
+
+use crate::audit::AuditSource;
+use crate::chat::ChatHandlerPacket;
+use crate::commands::players::progress_lines;
+use crate::commands::{
+    invoking_player, required, ArgKind, Args, Command, CommandContext, CommandError,
+    CommandResult,
+};
+use crate::console::{session_status, ConsolePacket};
+use crate::ranks::Rank;
+
+pub const SPAWN: Command = Command {
+    name: "spawn",
+    description: "teleport back to spawn",
+    permission: Rank::Default,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(spawn),
+};
+
+pub const STATS: Command = Command {
+    name: "stats",
+    description: "show your grip item, unlocked blocks and mined counts",
+    permission: Rank::Default,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(stats),
+};
+
+pub const MENU: Command = Command {
+    name: "menu",
+    description: "open the main menu",
+    permission: Rank::Default,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(menu),
+};
+
+pub const MSG: Command = Command {
+    name: "msg",
+    description: "send a private message to an online player",
+    permission: Rank::Default,
+    args: &[
+        required("player", ArgKind::Player),
+        required("message", ArgKind::Text),
+    ],
+    log_args: false,
+    subcommands: &[],
+    handler: Some(direct_message),
+};
+
+fn spawn(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    let (_, handle) = invoking_player(ctx)?;
+    let _ = handle.send(ConsolePacket::SendToSpawn);
+    ctx.reply("Teleported to spawn.");
+    Ok(())
+}
+
+fn stats(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    let (_, handle) = invoking_player(ctx)?;
+    ctx.defer(async move {
+        match session_status(handle).await {
+            Some(status) => progress_lines(&status.info),
+            None => vec![],
+        }
+    });
+    Ok(())
+}
+
+fn menu(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    let (_, handle) = invoking_player(ctx)?;
+    let _ = handle.send(ConsolePacket::OpenMenu);
+    Ok(())
+}
+
+fn direct_message(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let player = args.player("player").unwrap();
+    let message = args.text("message").unwrap();
+    let (profile, _) = ctx
+        .state
+        .find_online_by_name(player)
+        .ok_or_else(|| CommandError::Failed(format!("{} is not online.", player)))?;
+    let from = match &ctx.source {
+        AuditSource::Player { name, .. } => name.clone(),
+        _ => "Console".to_string(),
+    };
+    let packet = ChatHandlerPacket::DirectMessage(
+        profile.id,
+        msg!(format!("[{} -> you] {}", from, message), "gray")
+            .italic(true)
+            .into(),
+    );
+    ctx.state
+        .chat
+        .send(packet)
+        .map_err(|_| CommandError::Failed("The chat handler is not running.".to_string()))?;
+    ctx.reply(echo(&ctx.source, &profile.name, message));
+    Ok(())
+}
+
+// Only a player gets their message echoed back; anywhere else the reply ends up in the log.
+fn echo(source: &AuditSource, to: &str, message: &str) -> String {
+    match source {
+        AuditSource::Player { .. } => format!("[you -> {}] {}", to, message),
+        _ => format!("Message sent to {}.", to),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use drax::prelude::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn console_echo_leaves_out_the_message() {
+        for source in [
+            AuditSource::Console,
+            AuditSource::Remote {
+                address: "127.0.0.1:40000".to_string(),
+            },
+            AuditSource::Script {
+                name: "startup".to_string(),
+            },
+        ] {
+            let reply = echo(&source, "Notch", "the secret base is at 100 64 -20");
+            assert_eq!(reply, "Message sent to Notch.");
+        }
+    }
+
+    #[test]
+    fn player_echo_shows_the_message() {
+        let source = AuditSource::Player {
+            uuid: Uuid::nil(),
+            name: "jeb_".to_string(),
+        };
+        assert_eq!(echo(&source, "Notch", "hi"), "[you -> Notch] hi");
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use crate::audit::{AuditEntry, AuditSource};
+use anyhow::Context;
+
+use crate::commands::{
+    online_player, optional, required, ArgKind, Args, Command, CommandContext, CommandError,
+    CommandResult, PlayerLookup,
+};
+use crate::console::{kick_and_wait, session_status};
+use crate::db::{DbHook, PlayerDbInformation};
+use crate::game::blocks::GLOBAL_BLOCK_REGISTRY;
+use crate::ranks::Rank;
+
+pub const LIST: Command = Command {
+    name: "list",
+    description: "list online players with their rank and session time",
+    permission: Rank::Staff,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(list),
+};
+
+pub const INSPECT: Command = Command {
+    name: "inspect",
+    description: "show a player's rank, grip item, unlocked blocks and mined counts",
+    permission: Rank::Staff,
+    args: &[required("player", ArgKind::Player)],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(inspect),
+};
+
+pub const KICK: Command = Command {
+    name: "kick",
+    description: "disconnect a player, saving their progress",
+    permission: Rank::Staff,
+    args: &[
+        required("player", ArgKind::Player),
+        optional("reason", ArgKind::Text),
+    ],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(kick),
+};
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, secs) => format!("{}s", secs),
+        (0, mins, secs) => format!("{}m {:02}s", mins, secs),
+        (hours, mins, _) => format!("{}h {:02}m", hours, mins),
+    }
+}
+
+// Grip item, unlocked blocks and mined counts; shared with `/stats`.
+pub fn progress_lines(info: &PlayerDbInformation) -> Vec<String> {
+    let registry = GLOBAL_BLOCK_REGISTRY.current();
+    let block_name = |ordinal: usize| match registry.search_by_ordinal(ordinal) {
+        Some(block) => block.friendly_name.clone(),
+        None => format!("unknown #{}", ordinal),
+    };
+    let unlocked = info
+        .block_data
+        .unlocked_blocks
+        .iter()
+        .map(|block| block_name(block.block_ordinal))
+        .collect::<Vec<_>>();
+    let mined = info
+        .block_data
+        .mined_blocks
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(ordinal, count)| format!("{} x{}", block_name(ordinal), count))
+        .collect::<Vec<_>>();
+    let mined = if mined.is_empty() {
+        "nothing yet".to_string()
+    } else {
+        mined.join(", ")
+    };
+    vec![
+        format!(
+            "Grip item: #{} ({})",
+            info.grip_item.ordinal(),
+            info.grip_item.item_path()
+        ),
+        format!("Unlocked blocks: {}", unlocked.join(", ")),
+        format!("Mined: {}", mined),
+    ]
+}
+
+fn describe(info: &PlayerDbInformation, online_for: Option<Duration>) -> Vec<String> {
+    let session = match online_for {
+        Some(online_for) => format!("online for {}", format_duration(online_for)),
+        None => "offline".to_string(),
+    };
+    let mut lines = vec![format!(
+        "{} ({}) - {:?}, {}",
+        info.name, info.uuid, info.rank, session
+    )];
+    lines.extend(progress_lines(info));
+    lines
+}
+
+fn list(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    // Ask every session at once; they answer on their next tick.
+    let queries = ctx
+        .state
+        .online()
+        .map(|(profile, handle)| (profile.name.clone(), session_status(handle.clone())))
+        .collect::<Vec<_>>();
+    ctx.defer(async move {
+        let (names, queries): (Vec<_>, Vec<_>) = queries.into_iter().unzip();
+        let mut lines = vec![];
+        for (name, status) in names.into_iter().zip(futures::future::join_all(queries).await) {
+            if let Some(status) = status {
+                lines.push(format!(
+                    "  {} [{:?}] online for {}",
+                    name,
+                    status.info.rank,
+                    format_duration(status.online_for)
+                ));
+            }
+        }
+        lines.insert(0, format!("{} player(s) online.", lines.len()));
+        lines
+    });
+    Ok(())
+}
+
+fn inspect(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let player = args.player("player").unwrap();
+    if let Some((_, handle)) = online_player(ctx, player) {
+        let handle = handle.clone();
+        ctx.defer(async move {
+            match session_status(handle).await {
+                Some(status) => describe(&status.info, Some(status.online_for)),
+                None => vec!["The player left before their session answered.".to_string()],
+            }
+        });
+        return Ok(());
+    }
+    let lookup = PlayerLookup::new(ctx, player);
+    ctx.defer_try(async move {
+        let uuid = lookup.resolve().await?;
+        let info = tokio::task::spawn_blocking(move || DbHook::player(uuid).load())
+            .await
+            .map_err(|err| CommandError::Failed(format!("Player load panicked: {}", err)))?
+            .context("Failed to load the player")?
+            .ok_or_else(|| CommandError::Failed("That player has no stored data.".to_string()))?;
+        Ok(describe(&info, None))
+    });
+    Ok(())
+}
+
+fn kick(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let player = args.player("player").unwrap();
+    let reason = args.text("reason").unwrap_or("Kicked by an operator.").to_string();
+    let (profile, handle) = online_player(ctx, player)
+        .ok_or_else(|| CommandError::Failed(format!("{} is not online.", player)))?;
+    let (profile, handle) = (profile.clone(), handle.clone());
+    let (source, rank) = (ctx.source.clone(), ctx.rank);
+    ctx.defer_try(async move {
+        // Only the console may kick someone of the same or a higher rank.
+        if !matches!(source, AuditSource::Console) {
+            let left = format!("{} left before their session answered.", profile.name);
+            let status = session_status(handle.clone())
+                .await
+                .ok_or(CommandError::Failed(left))?;
+            if status.info.rank >= rank {
+                return Err(CommandError::Failed(format!(
+                    "You can't kick {}, whose rank is not below yours.",
+                    profile.name
+                )));
+            }
+        }
+        crate::audit::record(
+            AuditEntry::new(&source, "kick", reason.clone())
+                .target(profile.name.clone(), Some(profile.id)),
+        );
+        kick_and_wait(handle, &reason, true).await;
+        Ok(vec![format!("Kicked {}.", profile.name)])
+    });
+    Ok(())
+}
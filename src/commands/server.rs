@@ -0,0 +1,199 @@
+use mcprotocol::msg;
+
+use crate::audit::AuditEntry;
+use crate::chat::ChatHandlerPacket;
+use crate::commands::{
+    optional, required, ArgKind, Args, Command, CommandContext, CommandError, CommandResult,
+};
+use crate::console::ConsolePacket;
+use crate::ranks::Rank;
+
+pub const HELP: Command = Command {
+    name: "help",
+    description: "list commands, or show one command's usage",
+    permission: Rank::Default,
+    args: &[optional("command", ArgKind::Word)],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(help),
+};
+
+pub const STOP: Command = Command {
+    name: "stop",
+    description: "stop the server",
+    permission: Rank::Owner,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(stop),
+};
+
+pub const TPS: Command = Command {
+    name: "tps",
+    description: "show session tick times",
+    permission: Rank::Staff,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(tps),
+};
+
+pub const RELOAD: Command = Command {
+    name: "reload",
+    description: "reload data from disk",
+    permission: Rank::Owner,
+    args: &[],
+    log_args: true,
+    subcommands: &[Command {
+        name: "registries",
+        description: "reload block and grip item registries",
+        permission: Rank::Owner,
+        args: &[],
+        log_args: true,
+        subcommands: &[],
+        handler: Some(reload_registries),
+    }],
+    handler: None,
+};
+
+pub const BROADCAST: Command = Command {
+    name: "broadcast",
+    description: "send a message to every online player",
+    permission: Rank::Staff,
+    args: &[required("message", ArgKind::Text)],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(broadcast),
+};
+
+pub const SAVE_ALL: Command = Command {
+    name: "save-all",
+    description: "save every online player now",
+    permission: Rank::Staff,
+    args: &[],
+    log_args: true,
+    subcommands: &[],
+    handler: Some(save_all),
+};
+
+pub const JOBS: Command = Command {
+    name: "jobs",
+    description: "list scheduled jobs",
+    permission: Rank::Staff,
+    args: &[],
+    log_args: true,
+    subcommands: &[Command {
+        name: "cancel",
+        description: "cancel a scheduled job until the next restart",
+        permission: Rank::Owner,
+        args: &[required("job", ArgKind::Word)],
+        log_args: true,
+        subcommands: &[],
+        handler: Some(cancel_job),
+    }],
+    handler: Some(list_jobs),
+};
+
+fn help(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    for line in crate::commands::help_lines(ctx.rank, args.text("command")) {
+        ctx.reply(line);
+    }
+    Ok(())
+}
+
+fn stop(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    crate::audit::record(AuditEntry::new(&ctx.source, "stop", "server stop"));
+    ctx.state
+        .shutdown
+        .request(format!("Stop issued by {}", ctx.source));
+    ctx.reply("Stopping the server.");
+    Ok(())
+}
+
+fn tps(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    for line in crate::game::timings::report_lines() {
+        ctx.reply(line);
+    }
+    Ok(())
+}
+
+fn reload_registries(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    let result = crate::game::registry::reload_all();
+    let details = match &result {
+        Ok(()) => "reloaded".to_string(),
+        Err(err) => format!("failed: {:#}", err),
+    };
+    crate::audit::record(AuditEntry::new(&ctx.source, "reload", details));
+    result.map_err(|err| {
+        CommandError::from(err.context("Registry reload failed, keeping current registries"))
+    })?;
+    ctx.reply("Registries reloaded, online players will be refreshed.");
+    Ok(())
+}
+
+fn broadcast(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let message = args.text("message").unwrap();
+    let packet = ChatHandlerPacket::BroadcastMessage(
+        msg!(format!("[Broadcast] {}", message), "gold").into(),
+    );
+    ctx.state
+        .chat
+        .send(packet)
+        .map_err(|_| CommandError::Failed("The chat handler is not running.".to_string()))?;
+    crate::audit::record(AuditEntry::new(&ctx.source, "broadcast", message));
+    ctx.reply("Broadcast sent.");
+    Ok(())
+}
+
+// Every session queues a save, then the save worker is flushed so the data is on disk.
+fn save_all(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    let acks = ctx
+        .state
+        .online()
+        .filter_map(|(_, handle)| {
+            let (done, saved) = tokio::sync::oneshot::channel();
+            handle.send(ConsolePacket::Save(done)).ok().map(|_| saved)
+        })
+        .collect::<Vec<_>>();
+    let saves = ctx.state.saves.clone();
+    ctx.defer(async move {
+        let saved = futures::future::join_all(acks)
+            .await
+            .into_iter()
+            .filter(|ack| ack.is_ok())
+            .count();
+        if crate::db::flush(&saves).await {
+            vec![format!("Saved {} player(s).", saved)]
+        } else {
+            vec!["The save worker is not running; nothing was written.".to_string()]
+        }
+    });
+    Ok(())
+}
+
+fn list_jobs(ctx: &mut CommandContext, _: &Args) -> CommandResult {
+    let jobs = crate::scheduler::jobs();
+    if jobs.is_empty() {
+        ctx.reply("No scheduled jobs.");
+    }
+    for job in jobs {
+        let next_run = match job.next_run {
+            Some(next_run) => next_run.format("%Y-%m-%d %H:%M").to_string(),
+            None => "never".to_string(),
+        };
+        ctx.reply(format!(
+            "#{} {} [{}] `{}` - next {}, ran {} time(s)",
+            job.id, job.name, job.expression, job.command, next_run, job.runs
+        ));
+    }
+    Ok(())
+}
+
+fn cancel_job(ctx: &mut CommandContext, args: &Args) -> CommandResult {
+    let job = args.text("job").unwrap();
+    let name = crate::scheduler::cancel(job)
+        .ok_or_else(|| CommandError::Failed(format!("No scheduled job `{}`.", job)))?;
+    crate::audit::record(AuditEntry::new(&ctx.source, "cancel-job", name.clone()));
+    ctx.reply(format!("Cancelled job {} until the next restart.", name));
+    Ok(())
+}
@@ -0,0 +1,630 @@
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use log::LevelFilter;
+use mcprotocol::common::play::{Location, SimpleLocation};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::chat::is_valid_color;
+use crate::db::bundle::ConflictPolicy;
+use crate::logger::{LogFormat, LoggerOptions, RotationOptions};
+use crate::scheduler::CronSchedule;
+
+const DEFAULT_CONFIG_PATH: &'static str = "server.json";
+
+static SERVER_CONFIG: OnceLock<ServerConfig> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub proxy_protocol: bool,
+    pub chunk_radius: i32,
+    pub max_players: i32,
+    pub motd: MotdConfig,
+    pub spawn: SpawnConfig,
+    pub data_dir: PathBuf,
+    pub login: LoginConfig,
+    pub shutdown: ShutdownConfig,
+    pub registries: RegistriesConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub rcon: RconConfig,
+    pub console: ConsoleConfig,
+    pub scripts: ScriptsConfig,
+    pub storage: StorageConfig,
+    pub save_format: SaveFormat,
+    pub snapshots: SnapshotConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:25575".to_string(),
+            proxy_protocol: true,
+            chunk_radius: 8,
+            max_players: 20,
+            motd: Default::default(),
+            spawn: Default::default(),
+            data_dir: PathBuf::from("/home/minecraft/server/db"),
+            login: LoginConfig::Minehut,
+            shutdown: Default::default(),
+            registries: Default::default(),
+            logging: Default::default(),
+            metrics: Default::default(),
+            rcon: Default::default(),
+            console: Default::default(),
+            scripts: Default::default(),
+            storage: StorageConfig::Json,
+            save_format: SaveFormat::Json,
+            snapshots: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LoginConfig {
+    Minehut,
+    Offline,
+    Online { session_server: String },
+    Velocity { secret: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "backend", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StorageConfig {
+    Json,
+    // Relative to `data_dir`.
+    Sqlite { file: PathBuf },
+    Memory,
+}
+
+impl StorageConfig {
+    pub fn requires_json(&self) -> bool {
+        matches!(self, StorageConfig::Sqlite { .. })
+    }
+}
+
+// Only affects new writes; saves in either format are read back regardless.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveFormat {
+    Json,
+    Binary,
+    BinaryCompressed,
+}
+
+impl SaveFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(SaveFormat::Json),
+            "binary" => Some(SaveFormat::Binary),
+            "binary_compressed" => Some(SaveFormat::BinaryCompressed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub enabled: bool,
+    pub interval_mins: u64,
+    // Number of snapshots kept; the oldest are deleted first.
+    pub retention: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_mins: 60,
+            retention: 48,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    pub countdown_secs: u64,
+    // Used instead of `countdown_secs` for SIGINT/SIGTERM.
+    pub signal_countdown_secs: u64,
+    pub save_timeout_secs: u64,
+    pub kick_message: String,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            countdown_secs: 10,
+            signal_countdown_secs: 0,
+            save_timeout_secs: 30,
+            kick_message: "Server is restarting, please reconnect shortly.".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistriesConfig {
+    pub watch: bool,
+    pub watch_interval_secs: u64,
+}
+
+impl Default for RegistriesConfig {
+    fn default() -> Self {
+        Self {
+            watch: false,
+            watch_interval_secs: 5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: String,
+    pub format: LogFormat,
+    pub file: Option<PathBuf>,
+    pub archive_dir: Option<PathBuf>,
+    pub max_file_size_mb: Option<u64>,
+    pub rotate_daily: bool,
+    pub retention: usize,
+    pub compress: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            file: Some(PathBuf::from("logs/latest.log")),
+            archive_dir: None,
+            max_file_size_mb: Some(16),
+            rotate_daily: true,
+            retention: 14,
+            compress: true,
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn options(&self) -> LoggerOptions {
+        LoggerOptions {
+            log_level: self.level.parse().unwrap_or(LevelFilter::Info),
+            log_file: self.file.clone(),
+            rotation: RotationOptions {
+                archive_dir: self.archive_dir.clone(),
+                max_file_size: self.max_file_size_mb.map(|mb| mb * 1024 * 1024),
+                rotate_daily: self.rotate_daily,
+                retention: self.retention,
+                compress: self.compress,
+            },
+            format: self.format,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub bind: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:9464".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RconConfig {
+    pub enabled: bool,
+    // Loopback by default; RCON is plain text, so keep it off public interfaces.
+    pub bind: String,
+    pub password: String,
+}
+
+impl Default for RconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:25576".to_string(),
+            password: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsoleConfig {
+    // Line editing is only used when stdin and stdout are terminals, even if this is set.
+    pub interactive: bool,
+    // Relative to `data_dir`.
+    pub history_file: PathBuf,
+    pub history_size: usize,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            interactive: true,
+            history_file: PathBuf::from("console_history"),
+            history_size: 1000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptsConfig {
+    // Console commands run once at startup, one per line; relative to `data_dir`.
+    pub startup: Option<PathBuf>,
+    pub jobs: Vec<JobConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JobConfig {
+    pub name: String,
+    // Five-field cron expression in local time, or `@hourly`, `@daily`, `@weekly`, `@monthly`.
+    pub schedule: String,
+    pub command: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotdConfig {
+    pub title: String,
+    pub title_color: String,
+    pub subtitle: String,
+    pub subtitle_color: String,
+}
+
+impl Default for MotdConfig {
+    fn default() -> Self {
+        Self {
+            title: "Idle Game!\n".to_string(),
+            title_color: "#ffbbbb".to_string(),
+            subtitle: "Used For showing off ScrapyardRs".to_string(),
+            subtitle_color: "#bbbbff".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnConfig {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self {
+            x: 8.0,
+            y: 1.0,
+            z: 8.0,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl SpawnConfig {
+    pub fn location(&self) -> Location {
+        Location {
+            inner_loc: SimpleLocation {
+                x: self.x,
+                y: self.y,
+                z: self.z,
+            },
+            yaw: self.yaw,
+            pitch: self.pitch,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigErrors(Vec<String>);
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} configuration error(s):", self.0.len())?;
+        for error in &self.0 {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl ServerConfig {
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut errors = vec![];
+
+        if self.bind.parse::<SocketAddr>().is_err() {
+            errors.push(format!(
+                "bind: `{}` is not a valid socket address (expected e.g. 0.0.0.0:25565)",
+                self.bind
+            ));
+        }
+        if !(2..=32).contains(&self.chunk_radius) {
+            errors.push(format!(
+                "chunk_radius: {} is out of range (2..=32)",
+                self.chunk_radius
+            ));
+        }
+        if self.max_players <= 0 {
+            errors.push(format!(
+                "max_players: {} must be greater than 0",
+                self.max_players
+            ));
+        }
+        if !is_valid_color(&self.motd.title_color) {
+            errors.push(format!(
+                "motd.title_color: `{}` is not a named color or #rrggbb",
+                self.motd.title_color
+            ));
+        }
+        if !is_valid_color(&self.motd.subtitle_color) {
+            errors.push(format!(
+                "motd.subtitle_color: `{}` is not a named color or #rrggbb",
+                self.motd.subtitle_color
+            ));
+        }
+        let spawn = &self.spawn;
+        if ![spawn.x, spawn.y, spawn.z].iter().all(|v| v.is_finite())
+            || !spawn.yaw.is_finite()
+            || !spawn.pitch.is_finite()
+        {
+            errors.push("spawn: coordinates must be finite numbers".to_string());
+        }
+        if let LoginConfig::Online { session_server } = &self.login {
+            if !session_server.starts_with("http://") && !session_server.starts_with("https://") {
+                errors.push(format!(
+                    "login.session_server: `{}` must be an http(s) url",
+                    session_server
+                ));
+            }
+        }
+        if let LoginConfig::Velocity { secret } = &self.login {
+            if secret.trim().is_empty() {
+                errors.push("login.secret: velocity forwarding secret must not be empty".to_string());
+            }
+            if self.proxy_protocol {
+                errors.push(
+                    "proxy_protocol: must be false with velocity login, which forwards the \
+                     player's address itself"
+                        .to_string(),
+                );
+            }
+        }
+        if self.storage.requires_json() && self.save_format != SaveFormat::Json {
+            errors.push(
+                "save_format: must be json with sqlite storage, whose `players` view reads the \
+                 records as JSON"
+                    .to_string(),
+            );
+        }
+        if self.shutdown.save_timeout_secs == 0 {
+            errors.push("shutdown.save_timeout_secs: must be greater than 0".to_string());
+        }
+        if self.registries.watch && self.registries.watch_interval_secs == 0 {
+            errors.push("registries.watch_interval_secs: must be greater than 0".to_string());
+        }
+        if self.logging.level.parse::<LevelFilter>().is_err() {
+            errors.push(format!(
+                "logging.level: `{}` is not one of off, error, warn, info, debug, trace",
+                self.logging.level
+            ));
+        }
+        if self.logging.max_file_size_mb == Some(0) {
+            errors.push("logging.max_file_size_mb: must be greater than 0".to_string());
+        }
+        if self.logging.retention == 0 {
+            errors.push("logging.retention: must keep at least 1 archive".to_string());
+        }
+        if self.metrics.enabled && self.metrics.bind.parse::<SocketAddr>().is_err() {
+            errors.push(format!(
+                "metrics.bind: `{}` is not a valid socket address",
+                self.metrics.bind
+            ));
+        }
+        if self.rcon.enabled {
+            if self.rcon.bind.parse::<SocketAddr>().is_err() {
+                errors.push(format!(
+                    "rcon.bind: `{}` is not a valid socket address",
+                    self.rcon.bind
+                ));
+            }
+            if self.rcon.password.is_empty() {
+                errors.push("rcon.password: must be set when rcon is enabled".to_string());
+            }
+        }
+        if self.console.interactive && self.console.history_size == 0 {
+            errors.push("console.history_size: must be greater than 0".to_string());
+        }
+        for (index, job) in self.scripts.jobs.iter().enumerate() {
+            if job.name.trim().is_empty() {
+                errors.push(format!("scripts.jobs[{}].name: must not be empty", index));
+            } else if self.scripts.jobs[..index]
+                .iter()
+                .any(|other| other.name.eq_ignore_ascii_case(&job.name))
+            {
+                errors.push(format!("scripts.jobs[{}].name: `{}` is used twice", index, job.name));
+            }
+            if let Err(err) = CronSchedule::parse(&job.schedule) {
+                errors.push(format!(
+                    "scripts.jobs[{}].schedule: `{}` is not valid: {}",
+                    index, job.schedule, err
+                ));
+            }
+            if job.command.trim().is_empty() {
+                errors.push(format!("scripts.jobs[{}].command: must not be empty", index));
+            }
+        }
+        if let StorageConfig::Sqlite { file } = &self.storage {
+            if file.as_os_str().is_empty() {
+                errors.push("storage.file: sqlite database file must not be empty".to_string());
+            }
+        }
+        if self.snapshots.enabled && self.snapshots.interval_mins == 0 {
+            errors.push("snapshots.interval_mins: must be greater than 0".to_string());
+        }
+        if self.snapshots.enabled && self.snapshots.retention == 0 {
+            errors.push("snapshots.retention: must keep at least one snapshot".to_string());
+        }
+        if self.data_dir.as_os_str().is_empty() {
+            errors.push("data_dir: must not be empty".to_string());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(errors))
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open config file {}", path.display()))?;
+        let config: ServerConfig = serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+pub struct LaunchArgs {
+    pub config_path: PathBuf,
+    pub explicit_config: bool,
+    pub check_data: bool,
+    pub data_tool: Option<DataTool>,
+}
+
+// One-off player data operations run instead of the server.
+pub enum DataTool {
+    Export {
+        file: PathBuf,
+        player: Option<String>,
+    },
+    Import {
+        file: PathBuf,
+        on_conflict: ConflictPolicy,
+    },
+    Delete {
+        player: String,
+    },
+}
+
+pub fn parse_args() -> anyhow::Result<LaunchArgs> {
+    let mut args = LaunchArgs {
+        config_path: PathBuf::from(DEFAULT_CONFIG_PATH),
+        explicit_config: false,
+        check_data: false,
+        data_tool: None,
+    };
+    let mut player = None;
+    let mut on_conflict = ConflictPolicy::Skip;
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-c" | "--config" => {
+                let path = iter
+                    .next()
+                    .with_context(|| format!("Missing path after {}", arg))?;
+                args.config_path = PathBuf::from(path);
+                args.explicit_config = true;
+            }
+            "--check-data" => args.check_data = true,
+            "--export" | "--import" | "--delete" => {
+                if args.data_tool.is_some() {
+                    anyhow::bail!("Only one of --export, --import and --delete can be given");
+                }
+                let value = iter
+                    .next()
+                    .with_context(|| format!("Missing value after {}", arg))?;
+                args.data_tool = Some(match arg.as_str() {
+                    "--export" => DataTool::Export {
+                        file: PathBuf::from(value),
+                        player: None,
+                    },
+                    "--import" => DataTool::Import {
+                        file: PathBuf::from(value),
+                        on_conflict: ConflictPolicy::Skip,
+                    },
+                    _ => DataTool::Delete { player: value },
+                });
+            }
+            "--player" => {
+                player = Some(iter.next().context("Missing player after --player")?);
+            }
+            "--on-conflict" => {
+                let policy = iter.next().context("Missing policy after --on-conflict")?;
+                on_conflict = ConflictPolicy::parse(&policy).with_context(|| {
+                    format!("Unknown conflict policy `{}` (skip, overwrite, fail)", policy)
+                })?;
+            }
+            "-h" | "--help" => {
+                println!("Usage: idle-game-1 [--config <path>] [--check-data]");
+                println!(
+                    "       idle-game-1 [--config <path>] --export <file> [--player <player>]"
+                );
+                println!(
+                    "       idle-game-1 [--config <path>] --import <file> [--on-conflict skip|overwrite|fail]"
+                );
+                println!("       idle-game-1 [--config <path>] --delete <player>");
+                std::process::exit(0);
+            }
+            _ => anyhow::bail!("Unrecognized argument `{}`", arg),
+        }
+    }
+
+    match &mut args.data_tool {
+        Some(DataTool::Export { player: target, .. }) => *target = player,
+        Some(DataTool::Import {
+            on_conflict: target,
+            ..
+        }) => *target = on_conflict,
+        _ if player.is_some() => anyhow::bail!("--player only applies to --export"),
+        _ => {}
+    }
+    Ok(args)
+}
+
+// An explicitly passed config must exist; the default path is written out on first launch.
+pub fn load(args: &LaunchArgs) -> anyhow::Result<&'static ServerConfig> {
+    let config = if args.explicit_config || args.config_path.exists() {
+        ServerConfig::load(&args.config_path)?
+    } else {
+        let config = ServerConfig::default();
+        let file = std::fs::File::create(&args.config_path).with_context(|| {
+            format!(
+                "Failed to write default config to {}",
+                args.config_path.display()
+            )
+        })?;
+        serde_json::to_writer_pretty(file, &config)?;
+        config
+    };
+    Ok(SERVER_CONFIG.get_or_init(|| config))
+}
+
+pub fn get() -> &'static ServerConfig {
+    SERVER_CONFIG.get().expect("Server config accessed before load.")
+}
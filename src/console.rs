@@ -1,25 +1,41 @@
-use std::fmt::{Display, Formatter};
-use std::future::Future;
-use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::db::ensure_db;
-use bytes::{Buf, BytesMut};
+use drax::prelude::Uuid;
 use mcprotocol::common::GameProfile;
-use pin_project_lite::pin_project;
-use tokio::io::Stdin;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio_util::io::poll_read_buf;
+use tokio::sync::oneshot;
 
+use crate::chat::ChatHandlerPacket;
+use crate::commands::{CommandContext, CommandRequest};
+use crate::db::{PlayerDbInformation, SavePacket};
+use crate::metrics::METRICS;
 use crate::ranks::Rank;
+use crate::shutdown::ShutdownTrigger;
 
-pub fn attach_console() -> UnboundedSender<ConsoleHandle> {
+mod input;
+
+pub use input::restore_terminal;
+
+pub fn attach_console(
+    requests: UnboundedReceiver<CommandRequest>,
+    shutdown: ShutdownTrigger,
+    saves: UnboundedSender<SavePacket>,
+    chat: UnboundedSender<ChatHandlerPacket>,
+) -> UnboundedSender<ConsoleHandle> {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let online_names = Arc::new(Mutex::new(vec![]));
     let console = Console {
-        stdin: tokio::io::stdin(),
-        current_buffer: BytesMut::new(),
+        lines: input::spawn_input(&crate::config::get().console, online_names.clone()),
         recv: rx,
-        handles: Vec::new(),
+        requests,
+        online_names,
+        state: ConsoleState {
+            handles: Vec::new(),
+            shutdown,
+            saves,
+            chat,
+        },
     };
     tokio::spawn(async move {
         console.run().await;
@@ -29,174 +45,154 @@ pub fn attach_console() -> UnboundedSender<ConsoleHandle> {
 
 pub enum ConsolePacket {
     UpdateRank(Rank),
+    // `save: false` is for callers about to rewrite or remove the stored record themselves.
+    Kick {
+        reason: String,
+        save: bool,
+        done: oneshot::Sender<()>,
+    },
+    // Answered once the save has been handed to the save worker.
+    Save(oneshot::Sender<()>),
+    Status(oneshot::Sender<SessionStatus>),
+    SendToSpawn,
+    OpenMenu,
+}
+
+pub struct SessionStatus {
+    pub info: PlayerDbInformation,
+    pub online_for: Duration,
 }
 
 pub type ConsoleHandle = (GameProfile, UnboundedSender<ConsolePacket>);
 
-pub struct Console {
-    pub stdin: Stdin,
-    pub current_buffer: BytesMut,
-    pub recv: UnboundedReceiver<ConsoleHandle>,
+// What command handlers may touch; owned by the console task, which runs every command.
+pub struct ConsoleState {
     pub handles: Vec<ConsoleHandle>,
+    pub shutdown: ShutdownTrigger,
+    pub saves: UnboundedSender<SavePacket>,
+    pub chat: UnboundedSender<ChatHandlerPacket>,
 }
 
-impl Console {
-    pub fn poll(&mut self) -> ConsoleFuture {
-        ConsoleFuture {
-            stdin: &mut self.stdin,
-            current_buffer: &mut self.current_buffer,
-            recv: &mut self.recv,
-            handles: &mut self.handles,
-        }
+impl ConsoleState {
+    pub fn online(&self) -> impl Iterator<Item = &ConsoleHandle> {
+        self.handles.iter().filter(|(_, handle)| !handle.is_closed())
+    }
+
+    pub fn find_online(&self, uuid: Uuid) -> Option<UnboundedSender<ConsolePacket>> {
+        self.handles
+            .iter()
+            .find(|(profile, handle)| profile.id == uuid && !handle.is_closed())
+            .map(|(_, handle)| handle.clone())
     }
 
+    pub fn find_online_by_name(&self, name: &str) -> Option<&ConsoleHandle> {
+        self.handles.iter().find(|(profile, handle)| {
+            profile.name.eq_ignore_ascii_case(name) && !handle.is_closed()
+        })
+    }
+}
+
+pub struct Console {
+    pub lines: UnboundedReceiver<String>,
+    pub recv: UnboundedReceiver<ConsoleHandle>,
+    pub requests: UnboundedReceiver<CommandRequest>,
+    // Shared with the line editor for tab completion.
+    pub online_names: Arc<Mutex<Vec<String>>>,
+    pub state: ConsoleState,
+}
+
+enum ConsoleEvent {
+    Line(String),
+    Joined(ConsoleHandle),
+    Request(CommandRequest),
+}
+
+impl Console {
     pub async fn run(mut self) {
         loop {
-            let commands = match self.poll().await {
-                Ok(commands) => commands,
-                Err(ConsoleFutureError::FailedUtfRead) => continue,
-                Err(err) => {
-                    log::error!("Error in console: {}", err);
-                    continue;
-                }
+            let event = tokio::select! {
+                Some(line) = self.lines.recv() => ConsoleEvent::Line(line),
+                Some(handle) = self.recv.recv() => ConsoleEvent::Joined(handle),
+                Some(request) = self.requests.recv() => ConsoleEvent::Request(request),
+                else => break,
             };
 
-            for command in commands {
-                handle_command(command, &self.handles);
+            match event {
+                ConsoleEvent::Line(line) => self.execute(CommandRequest::console(line)),
+                ConsoleEvent::Joined(handle) => self.state.handles.push(handle),
+                ConsoleEvent::Request(request) => self.execute(request),
             }
+            self.state.handles.retain(|(_, handle)| !handle.is_closed());
+            *self.online_names.lock().unwrap() = self
+                .state
+                .handles
+                .iter()
+                .map(|(profile, _)| profile.name.clone())
+                .collect();
         }
     }
-}
-
-fn handle_command(command: String, handles: &Vec<ConsoleHandle>) {
-    log::info!("Handling command: {}", command);
 
-    let mut split_up = command.split(" ");
-    let command = match split_up.next() {
-        Some(command) => command,
-        None => return,
-    };
-    let args = split_up.collect::<Vec<_>>();
-    match command {
-        "rank" => handle_rank(args, handles),
-        "help" => {
-            log::info!("Available commands:");
-            log::info!("help - show this message");
-            log::info!("stop - stop the server");
-            log::info!("rank <player> <rank> - set a player's rank");
+    fn execute(&self, request: CommandRequest) {
+        if request.line.trim().is_empty() {
+            respond(request.reply, vec![]);
+            return;
         }
-        "stop" => std::process::exit(1),
-        _ => {
-            log::info!("Unrecognized command.");
+        log::info!(
+            "Handling command from {}: {}",
+            request.source,
+            crate::commands::log_line(&request.line)
+        );
+        METRICS.console_commands.inc();
+
+        let mut ctx = CommandContext::new(request.source, request.rank, &self.state);
+        crate::commands::dispatch(&mut ctx, &request.line);
+        let (output, deferred) = ctx.finish();
+        match (deferred, request.reply) {
+            (None, reply) => respond(reply, output),
+            // Console output shows up as it happens rather than after the slow part.
+            (Some(deferred), None) => {
+                respond(None, output);
+                tokio::spawn(async move { respond(None, deferred.await) });
+            }
+            (Some(deferred), Some(reply)) => {
+                tokio::spawn(async move {
+                    let mut output = output;
+                    output.extend(deferred.await);
+                    respond(Some(reply), output);
+                });
+            }
         }
     }
 }
 
-fn handle_rank(args: Vec<&str>, handles: &Vec<ConsoleHandle>) {
-    if args.len() != 2 {
-        println!("Usage: rank <player> <rank>");
-        return;
-    }
-    let player = args[0];
-    let rank = match args[1] {
-        "default" => Rank::Default,
-        "staff" => Rank::Staff,
-        "owner" => Rank::Owner,
-        _ => {
-            log::info!("Invalid rank.");
-            return;
+// Callers without a reply channel (the console itself) get their output in the log.
+fn respond(reply: Option<oneshot::Sender<Vec<String>>>, output: Vec<String>) {
+    match reply {
+        Some(reply) => {
+            let _ = reply.send(output);
         }
-    };
-    let mut found = false;
-    for (profile, handle) in handles {
-        if profile.name == player {
-            found = true;
-            let _ = handle.send(ConsolePacket::UpdateRank(rank));
-            log::info!("Updated player's rank!");
-            break;
+        None => {
+            for line in output {
+                log::info!("{}", line);
+            }
         }
     }
-    if !found {
-        log::info!("Could not find player {}.", player);
-    }
 }
 
-pin_project! {
-    pub struct ConsoleFuture<'a> {
-        stdin: &'a mut Stdin,
-        current_buffer: &'a mut BytesMut,
-        recv: &'a mut UnboundedReceiver<ConsoleHandle>,
-        handles: &'a mut Vec<ConsoleHandle>,
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum ConsoleFutureError {
-    RecvDropped,
-    StdinFailedRead,
-    FailedUtfRead,
-}
-
-impl Display for ConsoleFutureError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::RecvDropped => write!(f, "Console recv dropped"),
-            Self::StdinFailedRead => write!(f, "Stdin failed to read"),
-            Self::FailedUtfRead => write!(f, "Failed to read utf8"),
-        }
-    }
+// Resolves once the player's session has ended; a session that is already gone drops `done`.
+pub async fn kick_and_wait(handle: UnboundedSender<ConsolePacket>, reason: &str, save: bool) {
+    let (done, ended) = oneshot::channel();
+    let _ = handle.send(ConsolePacket::Kick {
+        reason: reason.to_string(),
+        save,
+        done,
+    });
+    let _ = ended.await;
 }
 
-impl std::error::Error for ConsoleFutureError {}
-
-impl<'a> Future for ConsoleFuture<'a> {
-    type Output = Result<Vec<String>, ConsoleFutureError>;
-
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let me = self.project();
-        me.handles.retain(|handle| !handle.1.is_closed());
-        if let Some(handle) = match me.recv.poll_recv(cx) {
-            Poll::Ready(Some(handle)) => Some(handle),
-            Poll::Ready(None) => return Poll::Ready(Err(ConsoleFutureError::RecvDropped)),
-            Poll::Pending => None,
-        } {
-            me.handles.push(handle);
-        }
-
-        loop {
-            match poll_read_buf(Pin::new(me.stdin), cx, me.current_buffer) {
-                Poll::Ready(Ok(_)) => {}
-                Poll::Ready(Err(_)) => {
-                    return Poll::Ready(Err(ConsoleFutureError::StdinFailedRead));
-                }
-                Poll::Pending => {
-                    return Poll::Pending;
-                }
-            }
-
-            let mut commands = vec![];
-            loop {
-                let mut iter = me.current_buffer.iter();
-                let next = iter.position(|b| *b == b'\n');
-                drop(iter);
-                if let Some(pos) = next {
-                    let pos = pos + 1;
-                    let mut take = me.current_buffer.take(pos);
-                    let str = match std::str::from_utf8(&take.chunk()[..pos - 1]) {
-                        Ok(str) => str,
-                        Err(_) => {
-                            return Poll::Ready(Err(ConsoleFutureError::FailedUtfRead));
-                        }
-                    };
-                    commands.push(str.to_string());
-                    take.advance(pos);
-                } else {
-                    break;
-                }
-            }
-            if !commands.is_empty() {
-                return Poll::Ready(Ok(commands));
-            }
-        }
-    }
+// `None` if the session ended before it could answer.
+pub async fn session_status(handle: UnboundedSender<ConsolePacket>) -> Option<SessionStatus> {
+    let (tx, rx) = oneshot::channel();
+    handle.send(ConsolePacket::Status(tx)).ok()?;
+    rx.await.ok()
 }
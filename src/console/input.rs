@@ -0,0 +1,178 @@
+use std::io::{BufRead, IsTerminal};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Config, Editor, ExternalPrinter, Helper};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::ConsoleConfig;
+
+// Reads console lines on a dedicated thread: a line editor when attached to a terminal, plain
+// line reads otherwise (pipes, containers, the Minehut injector).
+pub fn spawn_input(
+    config: &ConsoleConfig,
+    online_names: Arc<Mutex<Vec<String>>>,
+) -> tokio::sync::mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let interactive =
+        config.interactive && std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let history = crate::config::get().data_dir.join(&config.history_file);
+    let history_size = config.history_size;
+    std::thread::spawn(move || {
+        if interactive {
+            match read_interactive(&tx, history, history_size, online_names) {
+                Ok(()) => return,
+                Err(err) => {
+                    crate::logger::set_console_printer(None);
+                    log::warn!("Line editing unavailable, reading plain lines: {}", err);
+                }
+            }
+        }
+        read_plain(&tx);
+    });
+    rx
+}
+
+// The terminal settings from before the line editor took over. `readline` keeps the terminal in
+// raw mode while it waits, and nothing puts it back if the process exits during that wait.
+#[cfg(unix)]
+static SAVED_TERMINAL: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+#[cfg(unix)]
+fn save_terminal() {
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } == 0 {
+        *SAVED_TERMINAL.lock().unwrap() = Some(unsafe { termios.assume_init() });
+    }
+}
+
+// Stops the line editor's output and puts the terminal back the way it was; called right before
+// the process exits.
+pub fn restore_terminal() {
+    crate::logger::set_console_printer(None);
+    #[cfg(unix)]
+    restore_saved_terminal();
+}
+
+#[cfg(unix)]
+fn restore_saved_terminal() {
+    if let Some(termios) = SAVED_TERMINAL.lock().unwrap().take() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) };
+        // Keep the shell prompt off the editor's prompt line.
+        println!();
+    }
+}
+
+fn read_plain(lines: &UnboundedSender<String>) {
+    let mut stdin = std::io::stdin().lock();
+    let mut buf = vec![];
+    loop {
+        buf.clear();
+        match stdin.read_until(b'\n', &mut buf) {
+            // Nothing will ever arrive on a closed stdin; commands can still come in remotely.
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(err) => {
+                log::error!("Failed to read the console: {}", err);
+                return;
+            }
+        }
+        let line = match std::str::from_utf8(&buf) {
+            Ok(line) => line.to_string(),
+            Err(_) => {
+                log::warn!("Console input was not valid UTF-8; invalid bytes were replaced.");
+                String::from_utf8_lossy(&buf).into_owned()
+            }
+        };
+        if lines.send(line.trim_end_matches(['\r', '\n']).to_string()).is_err() {
+            return;
+        }
+    }
+}
+
+fn read_interactive(
+    lines: &UnboundedSender<String>,
+    history: PathBuf,
+    history_size: usize,
+    online_names: Arc<Mutex<Vec<String>>>,
+) -> rustyline::Result<()> {
+    let config = Config::builder()
+        .max_history_size(history_size)?
+        .history_ignore_dups(true)?
+        .auto_add_history(false)
+        .build();
+    #[cfg(unix)]
+    save_terminal();
+    let mut editor = Editor::<ConsoleHelper, FileHistory>::with_config(config)?;
+    editor.set_helper(Some(ConsoleHelper { online_names }));
+    if history.exists() {
+        if let Err(err) = editor.load_history(&history) {
+            log::warn!("Failed to load console history {}: {}", history.display(), err);
+        }
+    }
+    let mut printer = editor.create_external_printer()?;
+    crate::logger::set_console_printer(Some(Box::new(move |line| {
+        let _ = printer.print(line);
+    })));
+
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            // Ctrl-C doesn't raise SIGINT while the terminal is in raw mode.
+            Err(ReadlineError::Interrupted) => "stop".to_string(),
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                crate::logger::set_console_printer(None);
+                log::error!("Failed to read the console: {}", err);
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+            if let Err(err) = editor.append_history(&history) {
+                log::warn!("Failed to save console history {}: {}", history.display(), err);
+            }
+        }
+        if lines.send(line).is_err() {
+            break;
+        }
+    }
+    crate::logger::set_console_printer(None);
+    Ok(())
+}
+
+struct ConsoleHelper {
+    online_names: Arc<Mutex<Vec<String>>>,
+}
+
+impl Completer for ConsoleHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |space| space + 1);
+        let players = self.online_names.lock().unwrap();
+        let candidates = crate::commands::complete(&line[..start], &line[start..pos], &players);
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ConsoleHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ConsoleHelper {}
+
+impl Validator for ConsoleHelper {}
+
+impl Helper for ConsoleHelper {}
@@ -1,11 +1,33 @@
-use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
 
+use anyhow::Context;
 use drax::prelude::Uuid;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
 
+use crate::config::{SaveFormat, StorageConfig};
 use crate::game::grip_item::GripItem;
+use crate::metrics::METRICS;
 use crate::ranks::Rank;
+use binary::CompactEncoding;
+use schema::{SchemaError, Versioned};
+
+pub mod binary;
+pub mod bundle;
+mod json_file;
+mod memory;
+pub mod names;
+pub mod schema;
+pub mod snapshots;
+mod sqlite;
+mod writer;
+
+pub use json_file::JsonFileStorage;
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+pub use writer::{create_save_worker, flush, SavePacket};
 
 #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug, Clone)]
 pub struct PlayerDbInformation {
@@ -16,55 +38,330 @@ pub struct PlayerDbInformation {
     pub grip_item: GripItem,
 }
 
-const DB_PATH: &'static str = "/home/minecraft/server/db";
-const PLAYER_DB_EXT: &'static str = "players";
+pub const PLAYER_COLLECTION: &'static str = "players";
+pub const QUARANTINE_COLLECTION: &'static str = "quarantine";
+
+// Records are opaque bytes grouped by collection; encoding is left to `DbHook`.
+pub trait StorageBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn read(&self, collection: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    fn write(&self, collection: &str, key: &str, data: &[u8]) -> anyhow::Result<()>;
 
-pub fn ensure_db() {
-    let db_path = Path::new(DB_PATH);
-    if !db_path.exists() {
-        std::fs::create_dir_all(db_path).unwrap();
+    fn delete(&self, collection: &str, key: &str) -> anyhow::Result<bool>;
+
+    fn keys(&self, collection: &str) -> anyhow::Result<Vec<String>>;
+
+    // The record as it was before the most recent write, if the backend keeps one.
+    fn read_backup(&self, _collection: &str, _key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(None)
     }
-    let player_db_path = db_path.join(PLAYER_DB_EXT);
-    if !player_db_path.exists() {
-        std::fs::create_dir_all(player_db_path).unwrap();
+
+    // Moves an unreadable record out of the way so it can be inspected by hand; returns the key
+    // it was filed under in the quarantine collection.
+    fn quarantine(&self, collection: &str, key: &str) -> anyhow::Result<Option<String>> {
+        let data = match self.read(collection, key)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let quarantine_key = format!(
+            "{}-{}-{}",
+            collection,
+            key,
+            chrono::Utc::now().format("%Y%m%d%H%M%S")
+        );
+        self.write(QUARANTINE_COLLECTION, &quarantine_key, &data)?;
+        self.delete(collection, key)?;
+        Ok(Some(quarantine_key))
     }
 }
 
+static STORAGE: OnceLock<Box<dyn StorageBackend>> = OnceLock::new();
+
+pub fn ensure_db() -> anyhow::Result<()> {
+    let config = crate::config::get();
+    std::fs::create_dir_all(&config.data_dir).with_context(|| {
+        format!("Failed to create data directory {}", config.data_dir.display())
+    })?;
+
+    let backend: Box<dyn StorageBackend> = match &config.storage {
+        StorageConfig::Json => Box::new(JsonFileStorage::open(config.data_dir.clone())?),
+        StorageConfig::Sqlite { file } => {
+            Box::new(SqliteStorage::open(&config.data_dir.join(file))?)
+        }
+        StorageConfig::Memory => Box::new(MemoryStorage::default()),
+    };
+    if STORAGE.set(backend).is_err() {
+        anyhow::bail!("Storage backend initialized twice");
+    }
+    Ok(())
+}
+
+// Players whose record is being replaced by a rollback, import or delete. They can't log in until
+// that finishes, or their session would load the old record and later save over the new one.
+static RESTORING: Mutex<Vec<Uuid>> = Mutex::new(Vec::new());
+
+// Logins for the players stay blocked until this is dropped.
+pub struct RestoreGuard {
+    uuids: Vec<Uuid>,
+}
+
+pub fn begin_restore(uuids: Vec<Uuid>) -> RestoreGuard {
+    RESTORING.lock().unwrap().extend(uuids.iter().copied());
+    RestoreGuard { uuids }
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        let mut restoring = RESTORING.lock().unwrap();
+        for uuid in &self.uuids {
+            if let Some(index) = restoring.iter().position(|pending| pending == uuid) {
+                restoring.swap_remove(index);
+            }
+        }
+    }
+}
+
+pub fn is_restoring(uuid: Uuid) -> bool {
+    RESTORING.lock().unwrap().contains(&uuid)
+}
+
+pub fn storage() -> &'static dyn StorageBackend {
+    STORAGE
+        .get()
+        .expect("Storage accessed before ensure_db.")
+        .as_ref()
+}
+
 pub struct DbHook<T> {
-    pub hook_path: PathBuf,
+    pub collection: &'static str,
+    pub key: String,
     _phantom_t: std::marker::PhantomData<T>,
 }
 
 impl DbHook<()> {
     pub fn player(id: Uuid) -> DbHook<PlayerDbInformation> {
-        let db_path = Path::new(DB_PATH);
-        let player_db_path = db_path.join(PLAYER_DB_EXT);
         DbHook {
-            hook_path: player_db_path.join(id.to_string()),
+            collection: PLAYER_COLLECTION,
+            key: id.to_string(),
             _phantom_t: Default::default(),
         }
     }
 }
 
-impl<T> DbHook<T> {
-    pub fn insert(&self, data: &T) -> serde_json::Result<()>
+pub fn too_new<T: Versioned>(collection: &str, key: &str, version: u32) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{}/{} has schema version {}, newer than the {} this server understands",
+        collection,
+        key,
+        version,
+        T::current_version()
+    )
+}
+
+pub fn unsupported_layout(collection: &str, key: &str, version: u32) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{}/{} is a binary record at schema version {}, which this server has no layout for",
+        collection,
+        key,
+        version
+    )
+}
+
+// The version-stamped JSON form of a record, as stored by the JSON format and in export bundles.
+pub fn to_document<T: Versioned + Serialize>(data: &T) -> anyhow::Result<Value> {
+    let mut value = serde_json::to_value(data)?;
+    schema::stamp::<T>(&mut value)?;
+    Ok(value)
+}
+
+// Returns the record along with the schema version it was stored at.
+pub fn from_document<T: Versioned + DeserializeOwned>(
+    mut value: Value,
+) -> Result<(T, u32), SchemaError> {
+    let version = schema::upgrade::<T>(&mut value)?;
+    let data = serde_json::from_value(value).map_err(|err| SchemaError::Malformed(err.into()))?;
+    Ok((data, version))
+}
+
+fn encode<T: Versioned + CompactEncoding + Serialize>(
+    data: &T,
+    format: SaveFormat,
+) -> anyhow::Result<Vec<u8>> {
+    match format {
+        SaveFormat::Json => Ok(serde_json::to_vec_pretty(&to_document(data)?)?),
+        SaveFormat::Binary => binary::encode(data, false),
+        SaveFormat::BinaryCompressed => binary::encode(data, true),
+    }
+}
+
+impl<T: Versioned + CompactEncoding> DbHook<T> {
+    pub fn insert(&self, data: &T) -> anyhow::Result<()>
+    where
+        T: Serialize,
+    {
+        self.insert_as(data, crate::config::get().save_format)
+    }
+
+    pub fn insert_as(&self, data: &T, format: SaveFormat) -> anyhow::Result<()>
     where
         T: Serialize,
     {
-        let mut file = std::fs::File::create(&self.hook_path).unwrap();
-        serde_json::to_writer_pretty(&mut file, data)?;
-        Ok(())
+        let started = Instant::now();
+        let result = encode(data, format)
+            .and_then(|bytes| storage().write(self.collection, &self.key, &bytes));
+        METRICS.db_save.observe(started.elapsed());
+        if result.is_err() {
+            METRICS.db_save_failures.inc();
+        }
+        result.with_context(|| format!("Failed to save {}/{}", self.collection, self.key))
     }
 
-    pub fn load(&self) -> serde_json::Result<Option<T>>
+    // Binary and JSON records can sit side by side; the header decides which decoder runs.
+    fn decode(&self, bytes: &[u8]) -> Result<T, SchemaError>
     where
         T: DeserializeOwned,
     {
-        if !self.hook_path.exists() {
-            return Ok(None);
+        if binary::is_binary(bytes) {
+            return binary::decode(bytes);
+        }
+        let value: Value =
+            serde_json::from_slice(bytes).map_err(|err| SchemaError::Malformed(err.into()))?;
+        let (data, version) = from_document(value)?;
+        if version < T::current_version() {
+            log::info!(
+                "Migrated {}/{} from schema version {} to {}.",
+                self.collection,
+                self.key,
+                version,
+                T::current_version()
+            );
         }
-        let mut file = std::fs::File::open(&self.hook_path).unwrap();
-        let data = serde_json::from_reader(&mut file)?;
         Ok(data)
     }
+
+    // Decodes the stored record without the repairs `load` makes: nothing is quarantined or
+    // restored, so bulk readers can skip a bad record and leave it for a player's next login.
+    pub fn read(&self) -> anyhow::Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let bytes = match storage().read(self.collection, &self.key)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        match self.decode(&bytes) {
+            Ok(data) => Ok(Some(data)),
+            Err(SchemaError::TooNew(version)) => {
+                Err(too_new::<T>(self.collection, &self.key, version))
+            }
+            Err(SchemaError::Unsupported(version)) => {
+                Err(unsupported_layout(self.collection, &self.key, version))
+            }
+            Err(SchemaError::Malformed(err)) => {
+                Err(err.context(format!("{}/{} is unreadable", self.collection, self.key)))
+            }
+        }
+    }
+
+    // A record that fails to parse is quarantined and replaced by its backup rather than
+    // surfacing as an error the moment it is touched.
+    pub fn load(&self) -> anyhow::Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let storage = storage();
+        let primary_error = match storage.read(self.collection, &self.key)? {
+            Some(bytes) => match self.decode(&bytes) {
+                Ok(data) => return Ok(Some(data)),
+                Err(SchemaError::TooNew(version)) => {
+                    return Err(too_new::<T>(self.collection, &self.key, version))
+                }
+                Err(SchemaError::Unsupported(version)) => {
+                    return Err(unsupported_layout(self.collection, &self.key, version))
+                }
+                Err(SchemaError::Malformed(err)) => Some(err),
+            },
+            None => None,
+        };
+
+        let backup = storage.read_backup(self.collection, &self.key)?;
+        if let Some(err) = &primary_error {
+            log::error!(
+                "ALERT: {}/{} is corrupt ({:#}); quarantining it and trying the backup.",
+                self.collection,
+                self.key,
+                err
+            );
+            if let Some(quarantined) = storage.quarantine(self.collection, &self.key)? {
+                log::error!("Corrupt record kept as {}/{}.", QUARANTINE_COLLECTION, quarantined);
+            }
+        }
+
+        let backup = match backup {
+            Some(backup) => backup,
+            None => {
+                return match primary_error {
+                    Some(err) => Err(err.context(format!(
+                        "{}/{} is corrupt and has no backup",
+                        self.collection, self.key
+                    ))),
+                    None => Ok(None),
+                }
+            }
+        };
+        let data = match self.decode(&backup) {
+            Ok(data) => data,
+            Err(SchemaError::TooNew(version)) => {
+                return Err(too_new::<T>(self.collection, &self.key, version))
+            }
+            Err(SchemaError::Unsupported(version)) => {
+                return Err(unsupported_layout(self.collection, &self.key, version))
+            }
+            Err(SchemaError::Malformed(err)) => {
+                return Err(err.context(format!(
+                    "{}/{} and its backup are both unreadable",
+                    self.collection, self.key
+                )))
+            }
+        };
+        storage.write(self.collection, &self.key, &backup)?;
+        log::warn!(
+            "Restored {}/{} from its backup; progress since the previous save is lost.",
+            self.collection,
+            self.key
+        );
+        Ok(Some(data))
+    }
+}
+
+// Rewrites every player record in `format`. Records that fail to load are left untouched (and
+// quarantined/restored by `load` as usual).
+// Returns how many records were converted, skipped because they vanished meanwhile, and failed.
+pub fn convert_players(format: SaveFormat) -> anyhow::Result<(usize, usize, usize)> {
+    if format != SaveFormat::Json && crate::config::get().storage.requires_json() {
+        anyhow::bail!("sqlite storage keeps player records as JSON for its `players` view");
+    }
+    let (mut converted, mut skipped, mut failed) = (0, 0, 0);
+    for key in storage().keys(PLAYER_COLLECTION)? {
+        let hook = DbHook::<PlayerDbInformation> {
+            collection: PLAYER_COLLECTION,
+            key,
+            _phantom_t: Default::default(),
+        };
+        let result = hook.load().and_then(|data| match data {
+            Some(data) => hook.insert_as(&data, format).map(|_| true),
+            None => Ok(false),
+        });
+        match result {
+            Ok(true) => converted += 1,
+            Ok(false) => skipped += 1,
+            Err(err) => {
+                log::error!("Failed to convert {}/{}: {:#}", hook.collection, hook.key, err);
+                failed += 1;
+            }
+        }
+    }
+    Ok((converted, skipped, failed))
 }
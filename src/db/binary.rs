@@ -0,0 +1,208 @@
+use std::io::{Read, Write};
+
+use anyhow::Context;
+use drax::prelude::Uuid;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::db::schema::{SchemaError, Versioned};
+use crate::db::PlayerDbInformation;
+use crate::ranks::Rank;
+
+// Layout: magic, flags, schema version (u32 le), then the bincode payload. JSON saves always
+// start with `{`, so the magic is enough to tell the two apart on load.
+const MAGIC: &'static [u8; 4] = b"IGB\x01";
+const FLAG_DEFLATE: u8 = 0x1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+pub trait CompactEncoding: Sized {
+    fn has_compact_layout(version: u32) -> bool;
+
+    fn encode_compact(&self) -> anyhow::Result<Vec<u8>>;
+
+    fn decode_compact(version: u32, payload: &[u8]) -> anyhow::Result<Self>;
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode<T: CompactEncoding + Versioned>(data: &T, compress: bool) -> anyhow::Result<Vec<u8>> {
+    let payload = data.encode_compact()?;
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(MAGIC);
+    out.push(if compress { FLAG_DEFLATE } else { 0 });
+    out.extend_from_slice(&T::current_version().to_le_bytes());
+    if compress {
+        let mut encoder = DeflateEncoder::new(out, Compression::default());
+        encoder.write_all(&payload)?;
+        Ok(encoder.finish()?)
+    } else {
+        out.extend_from_slice(&payload);
+        Ok(out)
+    }
+}
+
+pub fn decode<T: CompactEncoding + Versioned>(bytes: &[u8]) -> Result<T, SchemaError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(SchemaError::Malformed(anyhow::anyhow!(
+            "binary record is truncated"
+        )));
+    }
+    let flags = bytes[MAGIC.len()];
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&bytes[MAGIC.len() + 1..HEADER_LEN]);
+    let version = u32::from_le_bytes(version);
+    if version > T::current_version() {
+        return Err(SchemaError::TooNew(version));
+    }
+    if !T::has_compact_layout(version) {
+        return Err(SchemaError::Unsupported(version));
+    }
+
+    let body = &bytes[HEADER_LEN..];
+    let decoded = if flags & FLAG_DEFLATE != 0 {
+        let mut payload = vec![];
+        DeflateDecoder::new(body)
+            .read_to_end(&mut payload)
+            .context("failed to inflate binary record")
+            .and_then(|_| T::decode_compact(version, &payload))
+    } else {
+        T::decode_compact(version, body)
+    };
+    decoded.map_err(SchemaError::Malformed)
+}
+
+// Registry entries are stored by ordinal only and rebuilt from the registries on load.
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct CompactPlayerV1 {
+    uuid: Uuid,
+    name: String,
+    rank: Rank,
+    unlocked_blocks: Vec<usize>,
+    mined_blocks: Vec<u128>,
+    grip_item: usize,
+}
+
+impl CompactEncoding for PlayerDbInformation {
+    // Binary saves were introduced at schema version 1; a future version bump adds a compact
+    // struct for it and upgrades older ones in `decode_compact`.
+    fn has_compact_layout(version: u32) -> bool {
+        version == 1
+    }
+
+    fn encode_compact(&self) -> anyhow::Result<Vec<u8>> {
+        let compact = CompactPlayerV1 {
+            uuid: self.uuid,
+            name: self.name.clone(),
+            rank: self.rank,
+            unlocked_blocks: self
+                .block_data
+                .unlocked_blocks
+                .iter()
+                .map(|block| block.block_ordinal)
+                .collect(),
+            mined_blocks: self.block_data.mined_blocks.clone(),
+            grip_item: self.grip_item.ordinal(),
+        };
+        Ok(bincode::serialize(&compact)?)
+    }
+
+    fn decode_compact(version: u32, payload: &[u8]) -> anyhow::Result<Self> {
+        if !Self::has_compact_layout(version) {
+            anyhow::bail!("no binary layout for schema version {}", version);
+        }
+        let compact: CompactPlayerV1 = bincode::deserialize(payload)?;
+
+        let registries = crate::game::registry::current();
+        let block_registry = &registries.blocks;
+        let mut block_data = crate::game::blocks::PlayerBlockData::default();
+        for ordinal in compact.unlocked_blocks {
+            match block_registry.search_by_ordinal(ordinal) {
+                Some(available) => block_data.unlocked_blocks.push(available.block_data),
+                None => log::warn!(
+                    target: compact.name.as_str(),
+                    "Dropping unlocked block {}, it is no longer in the registry.",
+                    ordinal
+                ),
+            }
+        }
+        block_data.mined_blocks = compact.mined_blocks;
+
+        let grip_items = &registries.grip_items;
+        let grip_item = match grip_items.get(compact.grip_item) {
+            Some(grip_item) => grip_item.clone(),
+            None => {
+                log::warn!(
+                    target: compact.name.as_str(),
+                    "Grip item {} is no longer in the registry, falling back to 0.",
+                    compact.grip_item
+                );
+                grip_items
+                    .get(0)
+                    .context("grip item registry has no ordinal 0")?
+                    .clone()
+            }
+        };
+
+        Ok(PlayerDbInformation {
+            uuid: compact.uuid,
+            name: compact.name,
+            rank: compact.rank,
+            block_data,
+            grip_item,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::to_document;
+
+    fn player() -> PlayerDbInformation {
+        crate::game::registry::load_defaults();
+        let registries = crate::game::registry::current();
+        let mut block_data = crate::game::blocks::PlayerBlockData::default();
+        block_data
+            .unlocked_blocks
+            .push(registries.blocks.search_by_ordinal(0).unwrap().block_data);
+        block_data.mined_blocks = vec![12];
+        PlayerDbInformation {
+            uuid: Uuid::nil(),
+            name: "Pickaxe".to_string(),
+            rank: Rank::Staff,
+            block_data,
+            grip_item: registries.grip_items.get(0).unwrap().clone(),
+        }
+    }
+
+    fn assert_round_trip(compress: bool) {
+        let player = player();
+        let bytes = encode(&player, compress).unwrap();
+        assert!(is_binary(&bytes));
+        let decoded: PlayerDbInformation = decode(&bytes).ok().unwrap();
+        assert_eq!(to_document(&decoded).unwrap(), to_document(&player).unwrap());
+    }
+
+    #[test]
+    fn round_trips_plain() {
+        assert_round_trip(false);
+    }
+
+    #[test]
+    fn round_trips_deflated() {
+        assert_round_trip(true);
+    }
+
+    #[test]
+    fn reports_missing_layout() {
+        let mut bytes = encode(&player(), false).unwrap();
+        bytes[MAGIC.len() + 1..HEADER_LEN].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            decode::<PlayerDbInformation>(&bytes),
+            Err(SchemaError::Unsupported(0))
+        ));
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Context;
+use drax::prelude::Uuid;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::Value;
+
+use crate::audit::{AuditEntry, AuditSource};
+use crate::config::DataTool;
+use crate::db::schema::SchemaError;
+use crate::db::{
+    from_document, names, snapshots, storage, to_document, too_new, unsupported_layout, DbHook,
+    PlayerDbInformation, PLAYER_COLLECTION, QUARANTINE_COLLECTION,
+};
+
+const BUNDLE_FORMAT: &'static str = "idle-game-players";
+const BUNDLE_VERSION: u32 = 1;
+
+// Portable between instances and storage backends: every player is a version-stamped JSON
+// document, so a bundle from an older server is migrated on import.
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+pub struct PlayerBundle {
+    format: String,
+    version: u32,
+    exported_at: String,
+    players: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    // Keep the existing record.
+    Skip,
+    // Replace the existing record with the bundle's.
+    Overwrite,
+    // Import nothing if any player in the bundle already exists.
+    Fail,
+}
+
+impl ConflictPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(ConflictPolicy::Skip),
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "fail" => Some(ConflictPolicy::Fail),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub overwritten: usize,
+    pub skipped: usize,
+    // Players whose stored record couldn't be read; they are left alone.
+    pub unreadable: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub exported: usize,
+    // Records left out because they couldn't be read; see the log.
+    pub unreadable: usize,
+}
+
+// Audit and reply details, shared by the console commands and the command-line tool.
+impl ImportSummary {
+    pub fn describe(&self, file: &str) -> String {
+        let mut details = format!(
+            "{} new player(s), overwrote {}, skipped {} from {}",
+            self.imported, self.overwritten, self.skipped, file
+        );
+        if self.unreadable > 0 {
+            details.push_str(&format!(
+                ", left {} unreadable record(s) alone (see the log)",
+                self.unreadable
+            ));
+        }
+        details
+    }
+}
+
+impl ExportSummary {
+    pub fn describe(&self, file: &str) -> String {
+        let mut details = format!("{} player(s) to {}", self.exported, file);
+        if self.unreadable > 0 {
+            details.push_str(&format!(
+                ", left out {} unreadable record(s) (see the log)",
+                self.unreadable
+            ));
+        }
+        details
+    }
+}
+
+pub fn export(player: Option<Uuid>, path: &Path) -> anyhow::Result<ExportSummary> {
+    let (bundle, unreadable) = PlayerBundle::collect(player)?;
+    bundle.write(path, false)?;
+    Ok(ExportSummary {
+        exported: bundle.player_count(),
+        unreadable,
+    })
+}
+
+impl PlayerBundle {
+    // Also returns how many records were skipped as unreadable. Exporting one player fails
+    // outright instead, and goes through `load` so a corrupt record is repaired from its backup.
+    pub fn collect(player: Option<Uuid>) -> anyhow::Result<(Self, usize)> {
+        let uuids = match player {
+            Some(uuid) => vec![uuid],
+            None => storage()
+                .keys(PLAYER_COLLECTION)?
+                .iter()
+                .filter_map(|key| Uuid::parse_str(key).ok())
+                .collect(),
+        };
+
+        let mut players = Vec::with_capacity(uuids.len());
+        let mut unreadable = 0;
+        for uuid in uuids {
+            let hook = DbHook::player(uuid);
+            let info = if player.is_some() {
+                hook.load()?
+            } else {
+                match hook.read() {
+                    Ok(info) => info,
+                    Err(err) => {
+                        log::warn!("Leaving {} out of the bundle: {:#}", uuid, err);
+                        unreadable += 1;
+                        continue;
+                    }
+                }
+            };
+            match info {
+                Some(info) => players.push(to_document(&info)?),
+                None if player.is_some() => anyhow::bail!("No stored data for {}", uuid),
+                None => {}
+            }
+        }
+        let bundle = PlayerBundle {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            players,
+        };
+        Ok((bundle, unreadable))
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    // Written under a temp name and renamed, so a half-written bundle never looks complete.
+    pub fn write(&self, path: &Path, compress: bool) -> anyhow::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = std::path::PathBuf::from(temp);
+        let file = std::fs::File::create(&temp)
+            .with_context(|| format!("Failed to create {}", temp.display()))?;
+        let file = if compress {
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            serde_json::to_writer(&mut encoder, self)?;
+            encoder.finish()?
+        } else {
+            let mut writer = std::io::BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, self)?;
+            writer.into_inner().map_err(|err| err.into_error())?
+        };
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    // Gzipped bundles (snapshots) are detected by their magic bytes.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let mut bytes =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut inflated = vec![];
+            GzDecoder::new(bytes.as_slice())
+                .read_to_end(&mut inflated)
+                .with_context(|| format!("Failed to decompress {}", path.display()))?;
+            bytes = inflated;
+        }
+        let bundle: PlayerBundle = serde_json::from_slice(&bytes)
+            .with_context(|| format!("{} is not a player bundle", path.display()))?;
+        if bundle.format != BUNDLE_FORMAT {
+            anyhow::bail!(
+                "{} is a `{}` file, not a player bundle",
+                path.display(),
+                bundle.format
+            );
+        }
+        if bundle.version > BUNDLE_VERSION {
+            anyhow::bail!(
+                "{} is bundle version {}, newer than this server supports",
+                path.display(),
+                bundle.version
+            );
+        }
+        Ok(bundle)
+    }
+
+    // Every document is validated before anything is written.
+    fn decode(self) -> anyhow::Result<Vec<PlayerDbInformation>> {
+        let mut players = Vec::with_capacity(self.players.len());
+        let mut seen = HashSet::new();
+        for (index, document) in self.players.into_iter().enumerate() {
+            let player: PlayerDbInformation = match from_document(document) {
+                Ok((player, _)) => player,
+                Err(SchemaError::TooNew(version)) => {
+                    return Err(too_new::<PlayerDbInformation>(
+                        "bundle",
+                        &index.to_string(),
+                        version,
+                    ))
+                }
+                Err(SchemaError::Unsupported(version)) => {
+                    return Err(unsupported_layout("bundle", &index.to_string(), version))
+                }
+                Err(SchemaError::Malformed(err)) => {
+                    return Err(err.context(format!("Player {} in the bundle is invalid", index)))
+                }
+            };
+            if !seen.insert(player.uuid) {
+                anyhow::bail!("Player {} appears in the bundle twice", player.uuid);
+            }
+            players.push(player);
+        }
+        Ok(players)
+    }
+
+    pub fn find(&self, uuid: Uuid) -> anyhow::Result<Option<PlayerDbInformation>> {
+        let document = self.players.iter().find(|document| {
+            document.get("uuid").and_then(|id| id.as_str()) == Some(uuid.to_string().as_str())
+        });
+        match document {
+            Some(document) => match from_document(document.clone()) {
+                Ok((player, _)) => Ok(Some(player)),
+                Err(SchemaError::TooNew(version)) => Err(too_new::<PlayerDbInformation>(
+                    "bundle",
+                    &uuid.to_string(),
+                    version,
+                )),
+                Err(SchemaError::Unsupported(version)) => {
+                    Err(unsupported_layout("bundle", &uuid.to_string(), version))
+                }
+                Err(SchemaError::Malformed(err)) => Err(err),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Returns whether the player was in the bundle.
+    pub fn remove(&mut self, uuid: Uuid) -> bool {
+        let before = self.players.len();
+        let uuid = uuid.to_string();
+        self.players.retain(|document| {
+            document.get("uuid").and_then(|id| id.as_str()) != Some(uuid.as_str())
+        });
+        self.players.len() != before
+    }
+
+    pub fn uuids(&self) -> Vec<Uuid> {
+        self.players
+            .iter()
+            .filter_map(|document| document.get("uuid")?.as_str())
+            .filter_map(|uuid| Uuid::parse_str(uuid).ok())
+            .collect()
+    }
+
+    pub fn import(self, policy: ConflictPolicy) -> anyhow::Result<ImportSummary> {
+        let players = self.decode()?;
+        // An unreadable record is never overwritten, so it stays around to be repaired.
+        let mut existing = Vec::with_capacity(players.len());
+        for player in &players {
+            existing.push(DbHook::player(player.uuid).read().map_err(|err| {
+                log::warn!("Not importing {}: {:#}", player.uuid, err);
+            }));
+        }
+        if policy == ConflictPolicy::Fail {
+            let conflicts = players
+                .iter()
+                .zip(&existing)
+                .filter(|(_, existing)| matches!(existing, Ok(Some(_))))
+                .map(|(player, _)| player.name.as_str())
+                .collect::<Vec<_>>();
+            if !conflicts.is_empty() {
+                anyhow::bail!("Already stored: {}", conflicts.join(", "));
+            }
+        }
+
+        let mut summary = ImportSummary::default();
+        for (player, existing) in players.into_iter().zip(existing) {
+            let existing = match existing {
+                Ok(existing) => existing,
+                Err(()) => {
+                    summary.unreadable += 1;
+                    continue;
+                }
+            };
+            if existing.is_some() && policy == ConflictPolicy::Skip {
+                summary.skipped += 1;
+                continue;
+            }
+            DbHook::player(player.uuid).insert(&player)?;
+            names::record_login(
+                player.uuid,
+                &player.name,
+                existing.as_ref().map(|existing| existing.name.as_str()),
+            )?;
+            match existing {
+                Some(_) => summary.overwritten += 1,
+                None => summary.imported += 1,
+            }
+        }
+        Ok(summary)
+    }
+}
+
+// Permanent: the record, its backup, quarantined copies, snapshot entries and the name index
+// entry all go.
+pub fn delete_player(uuid: Uuid) -> anyhow::Result<bool> {
+    let name = match DbHook::player(uuid).load() {
+        Ok(existing) => existing.map(|existing| existing.name),
+        // A corrupt record can still be deleted; it just can't tell us its name.
+        Err(err) => {
+            log::warn!("Deleting unreadable record for {}: {:#}", uuid, err);
+            None
+        }
+    };
+    let removed = storage().delete(PLAYER_COLLECTION, &uuid.to_string())?;
+    let quarantined = purge_quarantine(uuid)?;
+    let snapshotted = snapshots::purge_player(uuid)?;
+    if let Some(name) = name {
+        names::forget(uuid, &name)?;
+    }
+    Ok(removed || quarantined > 0 || snapshotted > 0)
+}
+
+// Quarantined records are filed as `<collection>-<key>-<timestamp>`.
+fn purge_quarantine(uuid: Uuid) -> anyhow::Result<usize> {
+    let prefix = format!("{}-{}-", PLAYER_COLLECTION, uuid);
+    let mut purged = 0;
+    for key in storage().keys(QUARANTINE_COLLECTION)? {
+        if key.starts_with(&prefix) && storage().delete(QUARANTINE_COLLECTION, &key)? {
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+fn resolve_stored(player: &str) -> anyhow::Result<Uuid> {
+    names::resolve(player)?.with_context(|| format!("Unknown player {}", player))
+}
+
+// Entry point for `--export`, `--import` and `--delete`; returns the process exit code.
+pub fn run_tool(tool: &DataTool) -> i32 {
+    let result = match tool {
+        DataTool::Export { file, player } => player
+            .as_deref()
+            .map(resolve_stored)
+            .transpose()
+            .and_then(|player| export(player, file))
+            .map(|summary| {
+                let details = summary.describe(&file.display().to_string());
+                let entry = AuditEntry::new(&AuditSource::Tool, "export", details.clone());
+                crate::audit::record(entry);
+                log::info!("Exported {}.", details)
+            }),
+        DataTool::Import { file, on_conflict } => PlayerBundle::read(file)
+            .and_then(|bundle| bundle.import(*on_conflict))
+            .map(|summary| {
+                let details = summary.describe(&file.display().to_string());
+                let entry = AuditEntry::new(&AuditSource::Tool, "import", details.clone());
+                crate::audit::record(entry);
+                log::info!("Imported {}.", details)
+            }),
+        DataTool::Delete { player } => resolve_stored(player).and_then(|uuid| {
+            if delete_player(uuid)? {
+                crate::audit::record(
+                    AuditEntry::new(&AuditSource::Tool, "delete", "all stored data")
+                        .target(player.clone(), Some(uuid)),
+                );
+                log::info!("Deleted all stored data for {}.", player);
+            } else {
+                log::info!("{} had no stored data.", player);
+            }
+            Ok(())
+        }),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            log::error!("{:#}", err);
+            1
+        }
+    }
+}
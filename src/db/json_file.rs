@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::db::StorageBackend;
+
+// The original layout: one pretty-printed JSON file per record at `<data_dir>/<collection>/<key>`.
+pub struct JsonFileStorage {
+    root: PathBuf,
+}
+
+impl JsonFileStorage {
+    pub fn open(root: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(root.join(crate::db::PLAYER_COLLECTION))
+            .with_context(|| format!("Failed to create player directory in {}", root.display()))?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, collection: &str, key: &str) -> PathBuf {
+        self.root.join(collection).join(key)
+    }
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+fn read_optional(path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+impl StorageBackend for JsonFileStorage {
+    fn name(&self) -> &'static str {
+        "json file"
+    }
+
+    fn read(&self, collection: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        read_optional(&self.path(collection, key))
+    }
+
+    // The new contents are fully on disk under a temp name before anything is renamed, so a crash
+    // at any point leaves either the old record, the new one, or the `.bak` to fall back on.
+    fn write(&self, collection: &str, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        let path = self.path(collection, key);
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        std::fs::create_dir_all(dir)?;
+
+        let temp = with_extension(&path, "tmp");
+        let mut file =
+            File::create(&temp).with_context(|| format!("Failed to create {}", temp.display()))?;
+        file.write_all(data)
+            .and_then(|_| file.sync_all())
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+        drop(file);
+
+        if path.exists() {
+            let backup = with_extension(&path, "bak");
+            std::fs::rename(&path, &backup)
+                .with_context(|| format!("Failed to back up {}", path.display()))?;
+        }
+        std::fs::rename(&temp, &path)
+            .with_context(|| format!("Failed to move {} into place", temp.display()))?;
+        sync_dir(dir).with_context(|| format!("Failed to sync {}", dir.display()))
+    }
+
+    fn delete(&self, collection: &str, key: &str) -> anyhow::Result<bool> {
+        let path = self.path(collection, key);
+        let _ = std::fs::remove_file(with_extension(&path, "bak"));
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("Failed to delete {}", path.display())),
+        }
+    }
+
+    fn keys(&self, collection: &str) -> anyhow::Result<Vec<String>> {
+        let dir = self.root.join(collection);
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut keys = vec![];
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Skip `.tmp` leftovers from an interrupted write and `.bak` copies.
+            if entry.file_type()?.is_file() && !name.contains('.') {
+                keys.push(name);
+            }
+        }
+        Ok(keys)
+    }
+
+    fn read_backup(&self, collection: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        read_optional(&with_extension(&self.path(collection, key), "bak"))
+    }
+
+    // Renamed rather than copied, and the `.bak` is left where it is for the restore.
+    fn quarantine(&self, collection: &str, key: &str) -> anyhow::Result<Option<String>> {
+        let path = self.path(collection, key);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let quarantine_key = format!(
+            "{}-{}-{}",
+            collection,
+            key,
+            chrono::Utc::now().format("%Y%m%d%H%M%S")
+        );
+        let target = self.path(crate::db::QUARANTINE_COLLECTION, &quarantine_key);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&path, &target)
+            .with_context(|| format!("Failed to quarantine {}", path.display()))?;
+        Ok(Some(quarantine_key))
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::db::StorageBackend;
+
+// Nothing survives a restart; meant for tests and throwaway local instances.
+#[derive(Default)]
+pub struct MemoryStorage {
+    records: Mutex<HashMap<(String, String), Vec<u8>>>,
+}
+
+impl StorageBackend for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "in-memory"
+    }
+
+    fn read(&self, collection: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let records = self.records.lock().unwrap();
+        Ok(records
+            .get(&(collection.to_string(), key.to_string()))
+            .cloned())
+    }
+
+    fn write(&self, collection: &str, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        let mut records = self.records.lock().unwrap();
+        records.insert((collection.to_string(), key.to_string()), data.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, collection: &str, key: &str) -> anyhow::Result<bool> {
+        let mut records = self.records.lock().unwrap();
+        Ok(records
+            .remove(&(collection.to_string(), key.to_string()))
+            .is_some())
+    }
+
+    fn keys(&self, collection: &str) -> anyhow::Result<Vec<String>> {
+        let records = self.records.lock().unwrap();
+        Ok(records
+            .keys()
+            .filter(|(record_collection, _)| record_collection == collection)
+            .map(|(_, key)| key.clone())
+            .collect())
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Context;
+use drax::prelude::Uuid;
+
+use crate::db::{storage, DbHook, PLAYER_COLLECTION};
+
+// `<lowercase name>` -> uuid, so the console can find players who aren't online.
+pub const NAME_COLLECTION: &'static str = "names";
+
+fn index_key(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+pub fn lookup(name: &str) -> anyhow::Result<Option<Uuid>> {
+    match storage().read(NAME_COLLECTION, &index_key(name))? {
+        Some(bytes) => {
+            let uuid = std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|uuid| Uuid::parse_str(uuid.trim()).ok())
+                .with_context(|| format!("Name index entry for {} is not a uuid", name))?;
+            Ok(Some(uuid))
+        }
+        None => Ok(None),
+    }
+}
+
+// Accepts either a uuid or a known player name.
+pub fn resolve(player: &str) -> anyhow::Result<Option<Uuid>> {
+    match Uuid::parse_str(player) {
+        Ok(uuid) => Ok(Some(uuid)),
+        Err(_) => lookup(player),
+    }
+}
+
+// Called on every login with the name from the player's previous save, so a renamed player's
+// old name stops resolving to them (unless someone else has taken it since).
+pub fn record_login(uuid: Uuid, name: &str, previous_name: Option<&str>) -> anyhow::Result<()> {
+    if let Some(previous_name) = previous_name {
+        if !previous_name.eq_ignore_ascii_case(name) && lookup(previous_name)? == Some(uuid) {
+            storage().delete(NAME_COLLECTION, &index_key(previous_name))?;
+            log::info!(target: name, "Name changed from {}.", previous_name);
+        }
+    }
+    if lookup(name)? != Some(uuid) {
+        storage().write(NAME_COLLECTION, &index_key(name), uuid.to_string().as_bytes())?;
+    }
+    Ok(())
+}
+
+// Databases from before the index existed get one built from the player records at startup.
+pub fn ensure_index() -> anyhow::Result<()> {
+    let storage = storage();
+    if !storage.keys(NAME_COLLECTION)?.is_empty() {
+        return Ok(());
+    }
+    let keys = storage.keys(PLAYER_COLLECTION)?;
+    if keys.is_empty() {
+        return Ok(());
+    }
+    log::info!("Building the player name index from {} record(s).", keys.len());
+    for key in keys {
+        let uuid = match Uuid::parse_str(&key) {
+            Ok(uuid) => uuid,
+            Err(_) => continue,
+        };
+        match DbHook::player(uuid).load() {
+            Ok(Some(player)) => record_login(uuid, &player.name, None)?,
+            Ok(None) => {}
+            Err(err) => log::warn!("Skipping {} in the name index: {:#}", key, err),
+        }
+    }
+    Ok(())
+}
+
+pub fn forget(uuid: Uuid, name: &str) -> anyhow::Result<()> {
+    if lookup(name)? == Some(uuid) {
+        storage().delete(NAME_COLLECTION, &index_key(name))?;
+    }
+    Ok(())
+}
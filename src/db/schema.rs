@@ -0,0 +1,163 @@
+use serde_json::Value;
+
+use crate::db::PlayerDbInformation;
+
+pub const VERSION_FIELD: &'static str = "schema_version";
+
+// Upgrades a document from the version at its index in `Versioned::MIGRATIONS` to the next one.
+pub type Migration = fn(&mut serde_json::Map<String, Value>) -> anyhow::Result<()>;
+
+pub trait Versioned {
+    const MIGRATIONS: &'static [Migration];
+
+    fn current_version() -> u32 {
+        Self::MIGRATIONS.len() as u32
+    }
+}
+
+pub enum SchemaError {
+    // The document is not something any version of the schema could have produced.
+    Malformed(anyhow::Error),
+    // Written by a newer server; left untouched so a downgrade doesn't destroy it.
+    TooNew(u32),
+    // A binary record at a version this build has no compact layout for. The record itself may
+    // be fine, so it is reported rather than quarantined.
+    Unsupported(u32),
+}
+
+pub fn stamp<T: Versioned>(value: &mut Value) -> anyhow::Result<()> {
+    match value.as_object_mut() {
+        Some(document) => {
+            document.insert(VERSION_FIELD.to_string(), Value::from(T::current_version()));
+            Ok(())
+        }
+        None => anyhow::bail!("Versioned records must serialize to a JSON object"),
+    }
+}
+
+// Runs every migration between the document's version and the current one, in order. Documents
+// saved before versioning existed have no version field and are treated as version 0.
+pub fn upgrade<T: Versioned>(value: &mut Value) -> Result<u32, SchemaError> {
+    let document = value.as_object_mut().ok_or_else(|| {
+        SchemaError::Malformed(anyhow::anyhow!("record is not a JSON object"))
+    })?;
+    let version = match document.get(VERSION_FIELD) {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .map(|version| version as u32)
+            .ok_or_else(|| {
+                SchemaError::Malformed(anyhow::anyhow!("`{}` is not a number", VERSION_FIELD))
+            })?,
+    };
+    if version > T::current_version() {
+        return Err(SchemaError::TooNew(version));
+    }
+    for (step, migration) in T::MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(document).map_err(|err| {
+            SchemaError::Malformed(err.context(format!(
+                "migration from version {} to {} failed",
+                step,
+                step + 1
+            )))
+        })?;
+    }
+    document.insert(VERSION_FIELD.to_string(), Value::from(T::current_version()));
+    Ok(version)
+}
+
+impl Versioned for PlayerDbInformation {
+    const MIGRATIONS: &'static [Migration] = &[player_v0_to_v1];
+}
+
+// v0 persisted the session's transient `block_data.changed` flag.
+fn player_v0_to_v1(document: &mut serde_json::Map<String, Value>) -> anyhow::Result<()> {
+    let block_data = document
+        .get_mut("block_data")
+        .and_then(|block_data| block_data.as_object_mut())
+        .ok_or_else(|| anyhow::anyhow!("missing `block_data`"))?;
+    block_data.remove("changed");
+    block_data
+        .entry("mined_blocks")
+        .or_insert_with(|| Value::Array(vec![]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    // A player saved before schema versioning: no version field and the old `changed` flag.
+    const PLAYER_V0: &'static str = include_str!("testdata/player-v0.json");
+
+    fn player_v0() -> Value {
+        serde_json::from_str(PLAYER_V0).unwrap()
+    }
+
+    #[test]
+    fn upgrades_v0_player() {
+        let mut value = player_v0();
+        assert!(value.get(VERSION_FIELD).is_none());
+
+        assert!(matches!(upgrade::<PlayerDbInformation>(&mut value), Ok(0)));
+        assert!(value["block_data"].get("changed").is_none());
+        assert_eq!(
+            value[VERSION_FIELD],
+            Value::from(PlayerDbInformation::current_version())
+        );
+
+        let (player, version) =
+            crate::db::from_document::<PlayerDbInformation>(value.clone()).ok().unwrap();
+        assert_eq!(version, PlayerDbInformation::current_version());
+        let saved = crate::db::to_document(&player).unwrap();
+        for field in ["uuid", "name", "rank", "block_data", VERSION_FIELD] {
+            assert_eq!(saved[field], value[field], "`{}` changed on round trip", field);
+        }
+    }
+
+    #[test]
+    fn upgrade_is_idempotent() {
+        let mut value = player_v0();
+        assert!(upgrade::<PlayerDbInformation>(&mut value).is_ok());
+        let upgraded = value.clone();
+
+        let current = PlayerDbInformation::current_version();
+        assert!(matches!(
+            upgrade::<PlayerDbInformation>(&mut value),
+            Ok(version) if version == current
+        ));
+        assert_eq!(value, upgraded);
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let mut value = player_v0();
+        let newer = PlayerDbInformation::current_version() + 1;
+        value[VERSION_FIELD] = Value::from(newer);
+        let original = value.clone();
+
+        assert!(matches!(
+            upgrade::<PlayerDbInformation>(&mut value),
+            Err(SchemaError::TooNew(version)) if version == newer
+        ));
+        assert_eq!(value, original, "a newer record must be left untouched");
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut value = player_v0();
+        value[VERSION_FIELD] = Value::from("two");
+        assert!(matches!(
+            upgrade::<PlayerDbInformation>(&mut value),
+            Err(SchemaError::Malformed(_))
+        ));
+
+        let mut value = Value::from(vec![1, 2, 3]);
+        assert!(matches!(
+            upgrade::<PlayerDbInformation>(&mut value),
+            Err(SchemaError::Malformed(_))
+        ));
+    }
+}
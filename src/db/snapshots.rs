@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use drax::prelude::Uuid;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::SnapshotConfig;
+use crate::db::bundle::PlayerBundle;
+use crate::db::{names, DbHook, PlayerDbInformation, SavePacket};
+
+const PREFIX: &'static str = "players-";
+const SUFFIX: &'static str = ".json.gz";
+
+// Snapshots are gzipped player bundles, so they work the same for every storage backend and can
+// also be fed to `import`.
+pub fn snapshot_dir() -> PathBuf {
+    crate::config::get().data_dir.join("snapshots")
+}
+
+fn snapshot_path(id: &str) -> PathBuf {
+    snapshot_dir().join(format!("{}{}{}", PREFIX, id, SUFFIX))
+}
+
+// Ids are UTC timestamps, so they sort oldest first.
+pub fn list() -> anyhow::Result<Vec<String>> {
+    let dir = snapshot_dir();
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut ids = vec![];
+    for entry in std::fs::read_dir(&dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(id) = name
+            .strip_prefix(PREFIX)
+            .and_then(|name| name.strip_suffix(SUFFIX))
+        {
+            ids.push(id.to_string());
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+// Held while a snapshot picks its id and is written, so a manual and a scheduled snapshot in the
+// same second can't land on the same file.
+static TAKING: Mutex<()> = Mutex::new(());
+
+// Returns the id, how many players went in and how many unreadable records were left out. A
+// bad record must not stop snapshots, since they are what a rollback needs.
+pub fn take_snapshot(retention: usize) -> anyhow::Result<(String, usize, usize)> {
+    let _taking = TAKING.lock().unwrap();
+    std::fs::create_dir_all(snapshot_dir())?;
+    let timestamp = chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    // A suffix keeps ids unique and still sorting oldest first.
+    let mut id = timestamp.clone();
+    let mut suffix = 0;
+    while snapshot_path(&id).exists() {
+        suffix += 1;
+        id = format!("{}-{}", timestamp, suffix);
+    }
+    let (bundle, unreadable) = PlayerBundle::collect(None)?;
+    bundle.write(&snapshot_path(&id), true)?;
+
+    let ids = list()?;
+    if ids.len() > retention {
+        for old in &ids[..ids.len() - retention] {
+            std::fs::remove_file(snapshot_path(old))
+                .with_context(|| format!("Failed to prune snapshot {}", old))?;
+        }
+    }
+    Ok((id, bundle.player_count(), unreadable))
+}
+
+pub fn find_player(id: &str, uuid: Uuid) -> anyhow::Result<PlayerDbInformation> {
+    let path = snapshot_path(id);
+    if !path.exists() {
+        anyhow::bail!("No snapshot named {}", id);
+    }
+    PlayerBundle::read(&path)?
+        .find(uuid)?
+        .with_context(|| format!("{} is not in snapshot {}", uuid, id))
+}
+
+// Rewrites every snapshot that holds the player without them; returns how many did.
+pub fn purge_player(uuid: Uuid) -> anyhow::Result<usize> {
+    let mut purged = 0;
+    for id in list()? {
+        let path = snapshot_path(&id);
+        let mut bundle = PlayerBundle::read(&path)?;
+        if bundle.remove(uuid) {
+            bundle
+                .write(&path, true)
+                .with_context(|| format!("Failed to purge {} from snapshot {}", uuid, id))?;
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+// The caller must make sure the player has no session that could save over the restored record.
+// The record being replaced is kept as the backup, so a rollback can itself be undone.
+pub fn restore_player(id: &str, uuid: Uuid) -> anyhow::Result<PlayerDbInformation> {
+    let restored = find_player(id, uuid)?;
+    let hook = DbHook::player(uuid);
+    let current = hook.load().unwrap_or_else(|err| {
+        log::warn!("Replacing unreadable record for {}: {:#}", uuid, err);
+        None
+    });
+    hook.insert(&restored)?;
+    names::record_login(
+        uuid,
+        &restored.name,
+        current.as_ref().map(|current| current.name.as_str()),
+    )?;
+    Ok(restored)
+}
+
+pub async fn snapshot_now(saves: &UnboundedSender<SavePacket>, retention: usize) {
+    // Include whatever online players have queued.
+    crate::db::flush(saves).await;
+    match tokio::task::spawn_blocking(move || take_snapshot(retention)).await {
+        Ok(Ok((id, count, 0))) => log::info!("Snapshot {} taken ({} player(s)).", id, count),
+        Ok(Ok((id, count, unreadable))) => log::error!(
+            "Snapshot {} taken ({} player(s)), but {} unreadable record(s) were left out; see \
+             the warnings above.",
+            id,
+            count,
+            unreadable
+        ),
+        Ok(Err(err)) => log::error!("Snapshot failed: {:#}", err),
+        Err(err) => log::error!("Snapshot panicked: {}", err),
+    }
+}
+
+pub fn spawn_snapshotter(saves: UnboundedSender<SavePacket>, config: SnapshotConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_mins * 60));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            snapshot_now(&saves, config.retention).await;
+        }
+    });
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::db::StorageBackend;
+
+const SCHEMA: &'static str = "
+CREATE TABLE IF NOT EXISTS records (
+    collection TEXT NOT NULL,
+    key TEXT NOT NULL,
+    data BLOB NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (collection, key)
+);
+CREATE TABLE IF NOT EXISTS backups (
+    collection TEXT NOT NULL,
+    key TEXT NOT NULL,
+    data BLOB NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (collection, key)
+);
+CREATE VIEW IF NOT EXISTS players AS
+    SELECT key AS uuid,
+           json_extract(CAST(data AS TEXT), '$.name') AS name,
+           json_extract(CAST(data AS TEXT), '$.rank') AS rank,
+           CAST(data AS TEXT) AS document,
+           updated_at
+    FROM records
+    WHERE collection = 'players';
+";
+
+// Embedded database; the `players` view is there for leaderboard and admin queries.
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open sqlite database {}", path.display()))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl StorageBackend for SqliteStorage {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn read(&self, collection: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection
+            .query_row(
+                "SELECT data FROM records WHERE collection = ?1 AND key = ?2",
+                params![collection, key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // Each write keeps the row it replaces in `backups`, in the same transaction.
+    fn write(&self, collection: &str, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO backups (collection, key, data, updated_at)
+             SELECT collection, key, data, updated_at FROM records
+             WHERE collection = ?1 AND key = ?2",
+            params![collection, key],
+        )?;
+        transaction.execute(
+            "INSERT INTO records (collection, key, data, updated_at)
+             VALUES (?1, ?2, ?3, strftime('%s', 'now'))
+             ON CONFLICT (collection, key)
+             DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+            params![collection, key, data],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn delete(&self, collection: &str, key: &str) -> anyhow::Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "DELETE FROM backups WHERE collection = ?1 AND key = ?2",
+            params![collection, key],
+        )?;
+        let removed = connection.execute(
+            "DELETE FROM records WHERE collection = ?1 AND key = ?2",
+            params![collection, key],
+        )?;
+        Ok(removed > 0)
+    }
+
+    fn keys(&self, collection: &str) -> anyhow::Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT key FROM records WHERE collection = ?1")?;
+        let keys = statement
+            .query_map(params![collection], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(keys)
+    }
+
+    fn read_backup(&self, collection: &str, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection
+            .query_row(
+                "SELECT data FROM backups WHERE collection = ?1 AND key = ?2",
+                params![collection, key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // The backup row has to outlive the quarantine, so this can't go through `delete`.
+    fn quarantine(&self, collection: &str, key: &str) -> anyhow::Result<Option<String>> {
+        let quarantine_key = format!(
+            "{}-{}-{}",
+            collection,
+            key,
+            chrono::Utc::now().format("%Y%m%d%H%M%S")
+        );
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let moved = transaction.execute(
+            "INSERT OR REPLACE INTO records (collection, key, data, updated_at)
+             SELECT ?3, ?4, data, updated_at FROM records
+             WHERE collection = ?1 AND key = ?2",
+            params![collection, key, crate::db::QUARANTINE_COLLECTION, quarantine_key],
+        )?;
+        transaction.execute(
+            "DELETE FROM records WHERE collection = ?1 AND key = ?2",
+            params![collection, key],
+        )?;
+        transaction.commit()?;
+        Ok(if moved > 0 { Some(quarantine_key) } else { None })
+    }
+}
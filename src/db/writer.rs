@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use drax::prelude::Uuid;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+use crate::db::{DbHook, PlayerDbInformation};
+
+pub enum SavePacket {
+    // `failures` hears back if this snapshot (or one that superseded it) could not be written.
+    Save {
+        info: PlayerDbInformation,
+        failures: UnboundedSender<String>,
+    },
+    // Answered once everything queued before it has been written.
+    Flush(oneshot::Sender<()>),
+}
+
+type Batch = HashMap<Uuid, (PlayerDbInformation, UnboundedSender<String>)>;
+
+pub fn create_save_worker() -> UnboundedSender<SavePacket> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(run_save_worker(rx));
+    tx
+}
+
+pub async fn flush(saves: &UnboundedSender<SavePacket>) -> bool {
+    let (tx, rx) = oneshot::channel();
+    saves.send(SavePacket::Flush(tx)).is_ok() && rx.await.is_ok()
+}
+
+fn queue(packet: SavePacket, batch: &mut Batch, flushes: &mut Vec<oneshot::Sender<()>>) {
+    match packet {
+        SavePacket::Save { info, failures } => {
+            batch.insert(info.uuid, (info, failures));
+        }
+        SavePacket::Flush(done) => flushes.push(done),
+    }
+}
+
+// Whatever piles up while a batch is being written is coalesced into the next one, so a player
+// saving faster than the disk keeps up only ever costs one write per batch.
+async fn run_save_worker(mut rx: UnboundedReceiver<SavePacket>) {
+    while let Some(first) = rx.recv().await {
+        let mut batch = Batch::new();
+        let mut flushes = vec![];
+        queue(first, &mut batch, &mut flushes);
+        while let Ok(next) = rx.try_recv() {
+            queue(next, &mut batch, &mut flushes);
+        }
+
+        if !batch.is_empty() {
+            let written = tokio::task::spawn_blocking(move || {
+                batch
+                    .into_iter()
+                    .map(|(uuid, (info, failures))| {
+                        (info.name.clone(), DbHook::player(uuid).insert(&info), failures)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await;
+            match written {
+                Ok(results) => {
+                    for (name, result, failures) in results {
+                        if let Err(err) = result {
+                            log::error!(target: name.as_str(), "Failed to save player data: {:#}", err);
+                            let _ = failures.send(format!("{:#}", err));
+                        }
+                    }
+                }
+                Err(err) => log::error!("Save batch panicked: {}", err),
+            }
+        }
+
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
@@ -1,14 +1,18 @@
 pub mod blocks;
 pub mod grip_item;
+pub mod registry;
 mod session;
 pub mod stateful;
 pub mod menus;
+pub mod timings;
 
 use crate::chat::ChatHandlerPacket;
 use crate::console::ConsolePacket;
+use crate::db::SavePacket;
 use crate::game::session::GameSession;
+use crate::shutdown::ShutdownListener;
 use mcprotocol::common::chunk::{CachedLevel, Chunk};
-use mcprotocol::common::play::{Location, SimpleLocation};
+use mcprotocol::common::play::Location;
 use mcprotocol::common::registry::RegistryKey;
 use shovel::phase::play::ConnectedPlayer;
 use shovel::system::{System, TickResult};
@@ -18,6 +22,8 @@ use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 pub struct ClientRouting {
     pub chat: UnboundedSender<ChatHandlerPacket>,
     pub console: UnboundedReceiver<ConsolePacket>,
+    pub shutdown: ShutdownListener,
+    pub saves: UnboundedSender<SavePacket>,
 }
 
 #[derive(Clone)]
@@ -32,10 +38,10 @@ pub struct GameFactory {
 }
 
 impl System for GameFactory {
-    type CreationDetails = ();
+    type CreationDetails = Location;
     type SplitOff = UnboundedSender<(ClientRouting, ConnectedPlayer)>;
 
-    fn create(_: Self::CreationDetails) -> (Self, Self::SplitOff) {
+    fn create(spawn: Self::CreationDetails) -> (Self, Self::SplitOff) {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
         let mut level = CachedLevel::default();
@@ -80,16 +86,6 @@ impl System for GameFactory {
             }
         }
 
-        let spawn = Location {
-            inner_loc: SimpleLocation {
-                x: 8.0,
-                y: 1.0,
-                z: 8.0,
-            },
-            yaw: 0.0,
-            pitch: 0.0,
-        };
-
         (
             Self {
                 initial_client_recv: rx,
@@ -3,16 +3,19 @@ use mcprotocol::clientbound::play::ClientboundPlayRegistry;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
+use std::path::Path;
 
+use anyhow::Context;
 use mcprotocol::common::play::{BlockPos, ItemStack};
 use mcprotocol::common::registry::RegistryKey;
-use mcprotocol::{combine, lock_static, msg};
+use mcprotocol::{combine, msg};
 use rand::prelude::SliceRandom;
 use shovel::entity::tracking::TrackableEntity;
 use shovel::inventory::item::ItemBuilder;
 use shovel::level::LevelMediator;
 use shovel::phase::play::ConnectedPlayer;
 
+use crate::game::registry::{Diagnostics, Reloadable};
 use crate::game::session::GameSessionPlayer;
 
 #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
@@ -34,24 +37,41 @@ pub struct GlobalBlockRegistry {
 }
 
 impl GlobalBlockRegistry {
-    pub fn create() -> Self {
-        let cached: CacheRegistry =
-            serde_json::from_slice(include_bytes!("./blocks-reg.json")).unwrap();
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read block registry {}", path.display()))?;
+        Self::from_slice(&bytes)
+            .with_context(|| format!("Invalid block registry {}", path.display()))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> anyhow::Result<Self> {
+        let cached: CacheRegistry = serde_json::from_slice(bytes)?;
         let mut available_blocks = HashMap::with_capacity(cached.items.len());
         for item in cached.items {
             let minecraft_block_tag = item.minecraft_block_tag;
 
+            let (block_id, item_id) = match (
+                RegistryKey::BlockStates.global(minecraft_block_tag.as_str()),
+                RegistryKey::Items.global(minecraft_block_tag.as_str()),
+            ) {
+                (Some(block_id), Some(item_id)) => (block_id, item_id),
+                _ => anyhow::bail!(
+                    "Block ordinal {} has unknown tag `{}`",
+                    item.block_ordinal,
+                    minecraft_block_tag
+                ),
+            };
+
             let data = AvailableBlockData {
                 block_ordinal: item.block_ordinal,
-                block_id: RegistryKey::BlockStates
-                    .global(minecraft_block_tag.as_str())
-                    .unwrap(),
-                item_id: RegistryKey::Items
-                    .global(minecraft_block_tag.as_str())
-                    .unwrap(),
+                block_id,
+                item_id,
                 initial_health: item.initial_health,
             };
 
+            if available_blocks.contains_key(&data) {
+                anyhow::bail!("Duplicate block ordinal {}", item.block_ordinal);
+            }
             available_blocks.insert(
                 data,
                 AvailableBlock {
@@ -62,7 +82,57 @@ impl GlobalBlockRegistry {
             );
         }
 
-        Self { available_blocks }
+        if !available_blocks.values().any(|block| block.is_default) {
+            anyhow::bail!("No default block is configured");
+        }
+
+        Ok(Self { available_blocks })
+    }
+
+    pub fn check(bytes: &[u8], diagnostics: &mut Diagnostics) {
+        let cached: CacheRegistry = match serde_json::from_slice(bytes) {
+            Ok(cached) => cached,
+            Err(err) => {
+                diagnostics.error(format!("malformed registry: {}", err));
+                return;
+            }
+        };
+        if cached.items.is_empty() {
+            diagnostics.error("registry has no blocks");
+            return;
+        }
+
+        for item in &cached.items {
+            let tag = item.minecraft_block_tag.as_str();
+            if RegistryKey::BlockStates.global(tag).is_none() {
+                diagnostics.error(format!(
+                    "block ordinal {}: unknown block tag `{}`",
+                    item.block_ordinal, tag
+                ));
+            }
+            if RegistryKey::Items.global(tag).is_none() {
+                diagnostics.error(format!(
+                    "block ordinal {}: `{}` has no item form for menus",
+                    item.block_ordinal, tag
+                ));
+            }
+            if item.initial_health == 0 {
+                diagnostics.error(format!(
+                    "block ordinal {}: initial_health must be greater than 0",
+                    item.block_ordinal
+                ));
+            }
+            if item.friendly_name.trim().is_empty() {
+                diagnostics.warning(format!(
+                    "block ordinal {}: friendly_name is empty",
+                    item.block_ordinal
+                ));
+            }
+        }
+        if !cached.items.iter().any(|item| item.is_default) {
+            diagnostics.error("no block has is_default set; new players would have nothing to mine");
+        }
+        diagnostics.check_ordinals("block", cached.items.iter().map(|item| item.block_ordinal));
     }
 
     pub fn search_by_ordinal(&self, ordinal: usize) -> Option<&AvailableBlock> {
@@ -80,7 +150,8 @@ impl GlobalBlockRegistry {
     }
 }
 
-lock_static!(GLOBAL_BLOCK_REGISTRY -> GlobalBlockRegistry => create);
+pub static GLOBAL_BLOCK_REGISTRY: Reloadable<GlobalBlockRegistry> =
+    Reloadable::new(|registries| &registries.blocks);
 
 #[derive(Clone, Copy, Debug, serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct AvailableBlockData {
@@ -142,6 +213,7 @@ impl PartialOrd for AvailableBlockData {
 pub struct PlayerBlockData {
     pub unlocked_blocks: Vec<AvailableBlockData>,
     pub mined_blocks: Vec<u128>,
+    #[serde(skip)]
     pub changed: bool,
 }
 
@@ -189,7 +261,14 @@ impl BlockSystem {
         if let Some(placed) = self.placed_blocks.get(&session.uuid()) {
             if let Some(block) = placed.get(&pos) {
                 if block.health == 0 {
-                    log::info!(target: session.username().as_str(), "Destroy was a complete success!");
+                    log::info!(
+                        target: session.username().as_str(),
+                        uuid:% = session.uuid(),
+                        x = pos.x,
+                        y = pos.y,
+                        z = pos.z;
+                        "Destroy was a complete success!"
+                    );
                     return true;
                 }
             }
@@ -265,7 +344,15 @@ impl BlockSystem {
 
             for placement in placements {
                 if !placed_blocks.contains_key(&placement) {
-                    log::info!("Populating placement: {:?}", placement);
+                    log::info!(
+                        target: session.username().as_str(),
+                        uuid:% = session.uuid(),
+                        x = placement.x,
+                        y = placement.y,
+                        z = placement.z;
+                        "Populating placement: {:?}",
+                        placement
+                    );
                     let block_to_place = session
                         .block_data
                         .unlocked_blocks
@@ -1,9 +1,14 @@
+use anyhow::Context;
 use drax::nbt::Tag;
 use mcprotocol::common::chat::Chat;
-use mcprotocol::{combine, lock_static, msg};
+use mcprotocol::common::registry::RegistryKey;
+use mcprotocol::{combine, msg};
 use serde_derive::{Deserialize, Serialize};
 use shovel::inventory::item::ItemBuilder;
 use std::collections::HashMap;
+use std::path::Path;
+
+use crate::game::registry::{Diagnostics, Reloadable};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GripItem {
@@ -15,6 +20,14 @@ pub struct GripItem {
 }
 
 impl GripItem {
+    pub fn ordinal(&self) -> usize {
+        self.ordinal
+    }
+
+    pub fn item_path(&self) -> &str {
+        &self.item_path
+    }
+
     pub fn create_item(&self) -> ItemBuilder {
         ItemBuilder::new(self.item_path.as_str())
             .display_name(self.item_name.clone())
@@ -45,19 +58,71 @@ pub struct GripItemRegistry {
     pub available_items: HashMap<usize, GripItem>,
 }
 
-lock_static!(GRIP_ITEM_REGISTRY -> GripItemRegistry => create);
+pub static GRIP_ITEM_REGISTRY: Reloadable<GripItemRegistry> =
+    Reloadable::new(|registries| &registries.grip_items);
 
 impl GripItemRegistry {
-    pub fn create() -> Self {
-        let available_items =
-            serde_json::from_slice::<CacheRegistry>(include_bytes!("./grip-item-reg.json"))
-                .unwrap()
-                .items
-                .into_iter()
-                .map(|item| (item.ordinal, item))
-                .collect();
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read grip item registry {}", path.display()))?;
+        Self::from_slice(&bytes)
+            .with_context(|| format!("Invalid grip item registry {}", path.display()))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut available_items = HashMap::new();
+        for item in serde_json::from_slice::<CacheRegistry>(bytes)?.items {
+            let ordinal = item.ordinal;
+            if available_items.insert(ordinal, item).is_some() {
+                anyhow::bail!("Duplicate grip item ordinal {}", ordinal);
+            }
+        }
+        if !available_items.contains_key(&0) {
+            anyhow::bail!("Grip item ordinal 0 (the starter item) is missing");
+        }
+
+        Ok(Self { available_items })
+    }
+
+    pub fn check(bytes: &[u8], diagnostics: &mut Diagnostics) {
+        let raw: serde_json::Value = match serde_json::from_slice(bytes) {
+            Ok(raw) => raw,
+            Err(err) => {
+                diagnostics.error(format!("malformed registry: {}", err));
+                return;
+            }
+        };
+        let cached: CacheRegistry = match serde_json::from_value(raw.clone()) {
+            Ok(cached) => cached,
+            Err(err) => {
+                diagnostics.error(format!("malformed registry: {}", err));
+                return;
+            }
+        };
 
-        Self { available_items }
+        let raw_items = raw["items"].as_array().cloned().unwrap_or_default();
+        for (item, raw_item) in cached.items.iter().zip(raw_items.iter()) {
+            let context = format!("grip item ordinal {}", item.ordinal);
+            if RegistryKey::Items.global(item.item_path.as_str()).is_none() {
+                diagnostics.error(format!(
+                    "{}: unknown item `{}`",
+                    context, item.item_path
+                ));
+            }
+            if item.damage == 0 {
+                diagnostics.warning(format!("{}: damage is 0", context));
+            }
+            diagnostics.check_chat_colors(&format!("{} item_name", context), &raw_item["item_name"]);
+            if let Some(lore) = raw_item["item_lore_parts"].as_array() {
+                for part in lore {
+                    diagnostics.check_chat_colors(&format!("{} item_lore_parts", context), part);
+                }
+            }
+        }
+        if !cached.items.iter().any(|item| item.ordinal == 0) {
+            diagnostics.error("grip item ordinal 0 (the starter item) is missing");
+        }
+        diagnostics.check_ordinals("grip item", cached.items.iter().map(|item| item.ordinal));
     }
 
     pub fn get(&self, ordinal: usize) -> Option<&GripItem> {
@@ -6,8 +6,9 @@ pub fn mined_statistics_page<C: Send + Sync>(data: &PlayerBlockData) -> Menu<C>
     let mut menu = Menu::from_rows(1, msg!("Mining Statistics", "aqua").bold(true), 1);
     let mut counter_x = 0;
     let mut counter_y = 0;
+    let registry = GLOBAL_BLOCK_REGISTRY.current();
     for (ordinal, count) in data.mined_blocks.iter().enumerate() {
-        if let Some(block) = GLOBAL_BLOCK_REGISTRY.search_by_ordinal(ordinal) {
+        if let Some(block) = registry.search_by_ordinal(ordinal) {
             let item = block.create_item(*count);
             menu.set_item_unaware(counter_x, counter_y, Some(item));
             counter_x += 1;
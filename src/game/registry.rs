@@ -0,0 +1,327 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+
+use crate::chat::is_valid_color;
+use crate::game::blocks::GlobalBlockRegistry;
+use crate::game::grip_item::GripItemRegistry;
+
+pub const BLOCK_REGISTRY_FILE: &'static str = "blocks-reg.json";
+pub const GRIP_ITEM_REGISTRY_FILE: &'static str = "grip-item-reg.json";
+
+const DEFAULT_BLOCK_REGISTRY: &'static [u8] = include_bytes!("./blocks-reg.json");
+const DEFAULT_GRIP_ITEM_REGISTRY: &'static [u8] = include_bytes!("./grip-item-reg.json");
+
+struct RegistryCheck {
+    file: &'static str,
+    default: &'static [u8],
+    check: fn(&[u8], &mut Diagnostics),
+}
+
+// Every registry file the server loads; new registries only need an entry here to be covered by
+// `--check-data` and reload validation.
+const REGISTRY_CHECKS: [RegistryCheck; 2] = [
+    RegistryCheck {
+        file: BLOCK_REGISTRY_FILE,
+        default: DEFAULT_BLOCK_REGISTRY,
+        check: GlobalBlockRegistry::check,
+    },
+    RegistryCheck {
+        file: GRIP_ITEM_REGISTRY_FILE,
+        default: DEFAULT_GRIP_ITEM_REGISTRY,
+        check: GripItemRegistry::check,
+    },
+];
+
+// Every registry lives in one set that is swapped as a whole, so nobody ever sees new blocks
+// with old grip items.
+pub struct Registries {
+    // Bumped on every successful reload so sessions know to refresh what they derived from the
+    // registries.
+    pub generation: u64,
+    pub blocks: GlobalBlockRegistry,
+    pub grip_items: GripItemRegistry,
+}
+
+static REGISTRIES: RwLock<Option<Arc<Registries>>> = RwLock::new(None);
+
+// Code that reads more than one registry should take one snapshot with this rather than calling
+// `current()` on each, which could straddle a reload.
+pub fn current() -> Arc<Registries> {
+    REGISTRIES
+        .read()
+        .unwrap()
+        .clone()
+        .expect("Registry accessed before it was loaded.")
+}
+
+// Installs the embedded default registries for tests that decode records against them.
+#[cfg(test)]
+pub fn load_defaults() {
+    let mut registries = REGISTRIES.write().unwrap();
+    if registries.is_none() {
+        *registries = Some(Arc::new(Registries {
+            generation: 1,
+            blocks: GlobalBlockRegistry::from_slice(DEFAULT_BLOCK_REGISTRY).unwrap(),
+            grip_items: GripItemRegistry::from_slice(DEFAULT_GRIP_ITEM_REGISTRY).unwrap(),
+        }));
+    }
+}
+
+pub fn generation() -> u64 {
+    REGISTRIES
+        .read()
+        .unwrap()
+        .as_ref()
+        .map_or(0, |registries| registries.generation)
+}
+
+// A view of one registry in the shared set.
+pub struct Reloadable<T: 'static> {
+    select: fn(&Registries) -> &T,
+}
+
+impl<T> Reloadable<T> {
+    pub const fn new(select: fn(&Registries) -> &T) -> Self {
+        Self { select }
+    }
+
+    pub fn current(&self) -> Current<T> {
+        Current {
+            registries: current(),
+            select: self.select,
+        }
+    }
+}
+
+pub struct Current<T: 'static> {
+    registries: Arc<Registries>,
+    select: fn(&Registries) -> &T,
+}
+
+impl<T> Deref for Current<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (self.select)(&self.registries)
+    }
+}
+
+pub fn registry_dir() -> PathBuf {
+    crate::config::get().data_dir.join("registries")
+}
+
+fn ensure_registry_file(path: &Path, default: &[u8]) -> anyhow::Result<()> {
+    if !path.exists() {
+        std::fs::write(path, default)
+            .with_context(|| format!("Failed to write default registry {}", path.display()))?;
+        log::info!("Wrote default registry to {}.", path.display());
+    }
+    Ok(())
+}
+
+pub fn ensure_registries() -> anyhow::Result<()> {
+    let dir = registry_dir();
+    std::fs::create_dir_all(&dir)?;
+    for registry in &REGISTRY_CHECKS {
+        ensure_registry_file(&dir.join(registry.file), registry.default)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub registry: &'static str,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "[{}] {}: {}", severity, self.registry, self.message)
+    }
+}
+
+#[derive(Default)]
+pub struct Diagnostics {
+    registry: &'static str,
+    pub entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    fn push(&mut self, severity: Severity, message: String) {
+        self.entries.push(Diagnostic {
+            severity,
+            registry: self.registry,
+            message,
+        });
+    }
+
+    pub fn error<S: Into<String>>(&mut self, message: S) {
+        self.push(Severity::Error, message.into());
+    }
+
+    pub fn warning<S: Into<String>>(&mut self, message: S) {
+        self.push(Severity::Warning, message.into());
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.entries.len() - self.error_count()
+    }
+
+    pub fn check_ordinals<I: Iterator<Item = usize>>(&mut self, kind: &str, ordinals: I) {
+        let mut ordinals = ordinals.collect::<Vec<_>>();
+        ordinals.sort_unstable();
+        let mut expected = 0;
+        for (index, ordinal) in ordinals.iter().enumerate() {
+            if index > 0 && ordinals[index - 1] == *ordinal {
+                self.error(format!("duplicate {} ordinal {}", kind, ordinal));
+                continue;
+            }
+            if *ordinal > expected {
+                self.warning(format!(
+                    "{} ordinals {}..{} are unused",
+                    kind, expected, ordinal
+                ));
+            }
+            expected = ordinal + 1;
+        }
+    }
+
+    // Walks a serialized chat component, including its `extra` children.
+    pub fn check_chat_colors(&mut self, context: &str, chat: &serde_json::Value) {
+        if let Some(color) = chat.get("color").and_then(|color| color.as_str()) {
+            if !is_valid_color(color) {
+                self.error(format!(
+                    "{}: `{}` is not a named color or #rrggbb",
+                    context, color
+                ));
+            }
+        }
+        if let Some(extra) = chat.get("extra").and_then(|extra| extra.as_array()) {
+            for child in extra {
+                self.check_chat_colors(context, child);
+            }
+        }
+    }
+}
+
+pub fn check_registries() -> Diagnostics {
+    let dir = registry_dir();
+    let mut diagnostics = Diagnostics::default();
+    for registry in &REGISTRY_CHECKS {
+        diagnostics.registry = registry.file;
+        let path = dir.join(registry.file);
+        match std::fs::read(&path) {
+            Ok(bytes) => (registry.check)(&bytes, &mut diagnostics),
+            Err(err) => diagnostics.error(format!("failed to read {}: {}", path.display(), err)),
+        }
+    }
+    diagnostics
+}
+
+// Entry point for `--check-data`; returns the process exit code.
+pub fn run_data_check() -> i32 {
+    println!("Checking registries in {}", registry_dir().display());
+    let diagnostics = check_registries();
+    for diagnostic in &diagnostics.entries {
+        println!("{}", diagnostic);
+    }
+    println!(
+        "{} error(s), {} warning(s).",
+        diagnostics.error_count(),
+        diagnostics.warning_count()
+    );
+    if diagnostics.error_count() > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+// Both registries are parsed and validated before either is swapped, so a bad edit to one file
+// never leaves the server running with half of a reload.
+pub fn reload_all() -> anyhow::Result<()> {
+    let diagnostics = check_registries();
+    for diagnostic in &diagnostics.entries {
+        match diagnostic.severity {
+            Severity::Warning => log::warn!("{}", diagnostic),
+            Severity::Error => log::error!("{}", diagnostic),
+        }
+    }
+    if diagnostics.error_count() > 0 {
+        anyhow::bail!(
+            "{} registry error(s), see above",
+            diagnostics.error_count()
+        );
+    }
+
+    let dir = registry_dir();
+    let blocks = GlobalBlockRegistry::load(&dir.join(BLOCK_REGISTRY_FILE))?;
+    let grip_items = GripItemRegistry::load(&dir.join(GRIP_ITEM_REGISTRY_FILE))?;
+
+    let generation = {
+        let mut registries = REGISTRIES.write().unwrap();
+        let generation = registries.as_ref().map_or(0, |current| current.generation) + 1;
+        *registries = Some(Arc::new(Registries {
+            generation,
+            blocks,
+            grip_items,
+        }));
+        generation
+    };
+    log::info!("Registries loaded (generation {}).", generation);
+    Ok(())
+}
+
+fn modified_times() -> Vec<Option<SystemTime>> {
+    let dir = registry_dir();
+    [BLOCK_REGISTRY_FILE, GRIP_ITEM_REGISTRY_FILE]
+        .iter()
+        .map(|file| {
+            std::fs::metadata(dir.join(file))
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .collect()
+}
+
+pub fn watch_registries(poll_interval: Duration) {
+    tokio::spawn(async move {
+        let mut last_seen = modified_times();
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            let current = modified_times();
+            if current == last_seen {
+                continue;
+            }
+            last_seen = current;
+            log::info!("Registry files changed on disk, reloading.");
+            if let Err(err) = reload_all() {
+                log::error!("Registry reload failed, keeping current registries: {:#}", err);
+            }
+        }
+    });
+}
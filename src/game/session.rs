@@ -1,29 +1,35 @@
 use std::ops::{Deref, DerefMut};
 use std::time::Duration;
 
-use mcprotocol::clientbound::play::ClientboundPlayRegistry::{InitializeBorder, TabList};
+use mcprotocol::clientbound::play::ClientboundPlayRegistry::{Disconnect, InitializeBorder, TabList};
 use mcprotocol::common::play::BlockPos;
 use mcprotocol::msg;
 use shovel::entity::tracking::{EntityData, EntityTracker, TrackableEntity};
 use shovel::inventory::item::ItemBuilder;
 use shovel::phase::play::ConnectedPlayer;
 use tokio::sync::mpsc::error::TryRecvError;
-use tokio::time::{interval, MissedTickBehavior};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::time::{interval, Instant, MissedTickBehavior};
 
 use crate::chat::ChatHandlerPacket;
-use crate::console::ConsolePacket;
-use crate::db::{DbHook, PlayerDbInformation};
-use crate::game::blocks::{BlockSystem, PlayerBlockData, GLOBAL_BLOCK_REGISTRY};
-use crate::game::grip_item::{GripItem, GRIP_ITEM_REGISTRY};
+use crate::console::{ConsolePacket, SessionStatus};
+use crate::db::{names, DbHook, PlayerDbInformation, SavePacket};
+use crate::game::blocks::{BlockSystem, PlayerBlockData};
+use crate::game::grip_item::GripItem;
+use crate::game::registry;
 use crate::game::stateful::{GlobPlayerState, StatefulEvent};
+use crate::game::timings::{TickPhase, TickTimings};
 use crate::game::{ClientRouting, GameLevel};
+use crate::metrics::METRICS;
 use crate::ranks::Rank;
 
 pub struct GameSessionPlayer {
     inner: ConnectedPlayer,
     pub current_tick: usize,
-    db_hook: DbHook<PlayerDbInformation>,
+    registry_generation: u64,
     routing: ClientRouting,
+    save_failures: (UnboundedSender<String>, UnboundedReceiver<String>),
     // extra data
     top_level_change: bool,
     pub rank: Rank,
@@ -31,6 +37,10 @@ pub struct GameSessionPlayer {
     pub grip_item: GripItem,
     // player state
     pub state: GlobPlayerState,
+    pub timings: TickTimings,
+    joined_at: Instant,
+    // Set once the console has kicked the player; answered when the session task has ended.
+    kicked: Option<oneshot::Sender<()>>,
 }
 
 impl Into<PlayerDbInformation> for &mut GameSessionPlayer {
@@ -66,9 +76,50 @@ impl GameSessionPlayer {
         self.top_level_change = false;
     }
 
+    pub fn disconnect(&mut self, reason: String) {
+        self.write_owned_packet(Disconnect {
+            reason: msg!(reason, "red").into(),
+        });
+    }
+
+    // Registry reloads swap the definitions out from under us; re-derive the grip item and the
+    // unlocked block stats from the new registries and redraw anything the client is showing.
+    fn refresh_registries(&mut self) {
+        let registries = registry::current();
+        self.registry_generation = registries.generation;
+
+        if let Some(grip_item) = registries.grip_items.get(self.grip_item.ordinal()) {
+            self.grip_item = grip_item.clone();
+        }
+        for block in self.block_data.unlocked_blocks.iter_mut() {
+            if let Some(available) = registries.blocks.search_by_ordinal(block.block_ordinal) {
+                *block = available.block_data;
+            }
+        }
+        self.top_level_change = true;
+
+        let current_grip_item = self.grip_item.create_item().build();
+        self.set_player_inventory_slot(Some(current_grip_item), 0, 3);
+        self.state.refresh_menu(&mut self.inner, &self.block_data);
+    }
+
+    // Hands a snapshot to the save worker; the write itself happens off the tick.
     pub fn save(&mut self) {
         let reserve: PlayerDbInformation = (self).into();
-        let _ = self.db_hook.insert(&reserve);
+        let packet = SavePacket::Save {
+            info: reserve,
+            failures: self.save_failures.0.clone(),
+        };
+        if self.routing.saves.send(packet).is_err() {
+            log::error!(target: self.target(), "Save worker is gone; player data was not saved.");
+        }
+    }
+
+    // The worker already logged the error; mark the player dirty so the next save retries.
+    fn check_save_failures(&mut self) {
+        while self.save_failures.1.try_recv().is_ok() {
+            self.top_level_change = true;
+        }
     }
 
     pub async fn tick(
@@ -91,8 +142,32 @@ impl GameSessionPlayer {
                         .send(ChatHandlerPacket::UpdateRank(self.uuid(), rank));
                     self.top_level_change = true;
                 }
+                ConsolePacket::Kick { reason, save, done } => {
+                    self.disconnect(reason);
+                    if save {
+                        self.save();
+                    }
+                    self.kicked = Some(done);
+                }
+                ConsolePacket::Save(done) => {
+                    self.save();
+                    self.unchanged();
+                    let _ = done.send(());
+                }
+                ConsolePacket::Status(reply) => {
+                    let info: PlayerDbInformation = (self).into();
+                    let _ = reply.send(SessionStatus {
+                        info,
+                        online_for: self.joined_at.elapsed(),
+                    });
+                }
+                ConsolePacket::SendToSpawn => self.teleport_local(world.spawn).await,
+                ConsolePacket::OpenMenu => self.state.open_menu(&mut self.inner, &self.block_data),
             }
         }
+        if self.kicked.is_some() {
+            return;
+        }
 
         if self.current_tick == 0 {
             self.render_proxy_level(&world.level, world.spawn).await;
@@ -131,25 +206,41 @@ impl GameSessionPlayer {
             setup_client_aesthetics(self).await;
         } else {
             self.current_tick += 1;
+            let phase_start = Instant::now();
             self.render_level(&world.level).await;
+            self.timings.record(TickPhase::Render, phase_start.elapsed());
         }
 
+        if self.registry_generation != registry::generation() {
+            self.refresh_registries();
+        }
+
+        self.check_save_failures();
         if self.current_tick % 100 == 0 && self.changed() {
+            let phase_start = Instant::now();
             self.save();
             self.unchanged();
+            self.timings.record(TickPhase::Save, phase_start.elapsed());
         }
 
+        let phase_start = Instant::now();
         block_system.tick_for(BlockPos { x: 8, y: 0, z: 24 }, self);
+        self.timings.record(TickPhase::Blocks, phase_start.elapsed());
 
-        for stateful_event in self.state.tick(
+        let phase_start = Instant::now();
+        let stateful_events = self.state.tick(
             &mut self.inner,
             self.current_tick,
             block_system,
             &world.level,
             &self.block_data,
-        ) {
+        );
+        self.timings.record(TickPhase::State, phase_start.elapsed());
+
+        for stateful_event in stateful_events {
             match stateful_event {
                 StatefulEvent::BlockBroken(_, block) => {
+                    METRICS.blocks_broken.inc(block.block_ordinal);
                     let mined = &mut self.block_data.mined_blocks;
                     if mined.len() <= block.block_ordinal {
                         mined.resize(block.block_ordinal + 1, 0);
@@ -198,9 +289,59 @@ pub struct GameSession {
 
 impl GameSession {
     pub fn new(routing: ClientRouting, player: ConnectedPlayer, world: GameLevel) {
-        let db_hook = DbHook::player(player.uuid());
-        let current = if db_hook.hook_path.exists() {
-            let current = db_hook.load().unwrap().unwrap();
+        tokio::spawn(Self::start(routing, player, world));
+    }
+
+    async fn start(routing: ClientRouting, mut player: ConnectedPlayer, world: GameLevel) {
+        // A quick reconnect can arrive while the disconnect save is still queued, so let the save
+        // worker catch up before reading the record back.
+        crate::db::flush(&routing.saves).await;
+        if crate::db::is_restoring(player.uuid()) {
+            player.write_owned_packet(Disconnect {
+                reason: msg!(
+                    "Your player data is being restored, please reconnect in a moment.",
+                    "red"
+                )
+                .into(),
+            });
+            return;
+        }
+        let (uuid, username) = (player.uuid(), player.username().to_string());
+        let loaded = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+            let loaded = DbHook::player(uuid).load()?;
+            if let Err(err) = names::record_login(
+                uuid,
+                &username,
+                loaded.as_ref().map(|current| current.name.as_str()),
+            ) {
+                log::warn!(target: username.as_str(), "Failed to update the name index: {:#}", err);
+            }
+            Ok(loaded)
+        })
+        .await
+        .unwrap_or_else(|err| Err(anyhow::anyhow!("player load panicked: {}", err)));
+        let loaded = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                // Never start over with a fresh save on top of data we couldn't read.
+                log::error!(
+                    target: player.username().as_str(),
+                    uuid:% = player.uuid();
+                    "ALERT: failed to load player data, refusing login: {:#}",
+                    err
+                );
+                player.write_owned_packet(Disconnect {
+                    reason: msg!(
+                        "Your save data could not be loaded. Please contact staff.",
+                        "red"
+                    )
+                    .into(),
+                });
+                return;
+            }
+        };
+        let registries = registry::current();
+        let current = if let Some(current) = loaded {
             if current.rank != Rank::Default {
                 let _ = routing
                     .chat
@@ -213,9 +354,9 @@ impl GameSession {
                 name: player.username().to_string(),
                 rank: Rank::Default,
                 block_data: Default::default(),
-                grip_item: GRIP_ITEM_REGISTRY.get(0).unwrap().clone(),
+                grip_item: registries.grip_items.get(0).unwrap().clone(),
             };
-            for reg_item in GLOBAL_BLOCK_REGISTRY.get_all() {
+            for reg_item in registries.blocks.get_all() {
                 if reg_item.is_default {
                     info.block_data.unlocked_blocks.push(reg_item.block_data);
                 }
@@ -223,34 +364,63 @@ impl GameSession {
             info
         };
 
+        let (player_uuid, player_name) = (player.uuid(), player.username().to_string());
         let mut game_session = GameSession {
             host: GameSessionPlayer {
-                db_hook,
                 inner: player,
                 current_tick: 0,
+                registry_generation: registries.generation,
                 block_data: current.block_data,
                 routing,
+                save_failures: tokio::sync::mpsc::unbounded_channel(),
                 top_level_change: false,
                 rank: current.rank,
                 grip_item: current.grip_item,
                 state: GlobPlayerState::default(),
+                timings: TickTimings::new(player_uuid, player_name),
+                joined_at: Instant::now(),
+                kicked: None,
             },
             world,
             tracker: Default::default(),
             block_system: BlockSystem::default(),
         };
 
-        tokio::spawn(async move {
-            let mut interval = interval(Duration::from_millis(50));
-            interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
-            loop {
-                if !game_session.tick().await {
-                    game_session.host.save();
-                    break;
-                }
-                interval.tick().await;
+        let session_guard = game_session.host.routing.shutdown.register_session();
+        METRICS.logins.inc();
+        METRICS.players_online.inc();
+        let tick_duration = Duration::from_millis(50);
+        let mut interval = interval(tick_duration);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
+        loop {
+            if let Some(reason) = game_session.host.routing.shutdown.kick_reason() {
+                game_session.host.disconnect(reason);
+                game_session.host.save();
+                break;
             }
-        });
+            let tick_start = Instant::now();
+            let active = game_session.tick().await;
+            let tick_time = tick_start.elapsed();
+            METRICS.session_tick.observe(tick_time);
+            game_session.host.timings.finish_tick(tick_time);
+            if game_session.host.kicked.is_some() {
+                break;
+            }
+            if !active {
+                game_session.host.save();
+                break;
+            }
+            let scheduled = interval.tick().await;
+            if scheduled.elapsed() >= tick_duration {
+                METRICS.missed_ticks.inc();
+            }
+        }
+        METRICS.players_online.dec();
+        METRICS.disconnects.inc();
+        drop(session_guard);
+        if let Some(done) = game_session.host.kicked.take() {
+            let _ = done.send(());
+        }
     }
 
     #[must_use]
@@ -46,6 +46,25 @@ pub struct GlobPlayerState {
 // }
 
 impl GlobPlayerState {
+    pub fn refresh_menu(&mut self, player: &mut ConnectedPlayer, block_data: &PlayerBlockData) {
+        if let MenuState::Other(_) = &self.current_menu {
+            let menu = super::menus::mined_statistics_page(block_data);
+            menu.send_to_player(player);
+            self.current_menu = MenuState::Other(menu);
+        }
+    }
+
+    pub fn open_menu(&mut self, player: &mut ConnectedPlayer, block_data: &PlayerBlockData) {
+        if let MenuState::Other(current) = &self.current_menu {
+            if current.container_id() == 1 {
+                return;
+            }
+        }
+        let menu = super::menus::mined_statistics_page(block_data);
+        menu.send_to_player(player);
+        self.current_menu = MenuState::Other(menu);
+    }
+
     pub fn tick(
         &mut self,
         player: &mut ConnectedPlayer,
@@ -100,16 +119,7 @@ impl GlobPlayerState {
                             0 => {
                                 // todo upgrade stuff
                             }
-                            8 => {
-                                if let MenuState::Other(current) = &self.current_menu {
-                                    if current.container_id() == 1 {
-                                        continue;
-                                    }
-                                }
-                                let menu = super::menus::mined_statistics_page(block_data);
-                                menu.send_to_player(player);
-                                self.current_menu = MenuState::Other(menu);
-                            }
+                            8 => self.open_menu(player, block_data),
                             _ => {}
                         }
                     }
@@ -125,16 +135,7 @@ impl GlobPlayerState {
                                     stateful_events.push(event);
                                 }
                             }
-                            8 => {
-                                if let MenuState::Other(current) = &self.current_menu {
-                                    if current.container_id() == 1 {
-                                        continue;
-                                    }
-                                }
-                                let menu = super::menus::mined_statistics_page(block_data);
-                                menu.send_to_player(player);
-                                self.current_menu = MenuState::Other(menu);
-                            }
+                            8 => self.open_menu(player, block_data),
                             _ => {}
                         }
                     }
@@ -209,7 +210,12 @@ impl GlobPlayerState {
                         .set_current_slot_unaware(slot as u8);
                 }
                 packet => {
-                    log::info!(target: player.username().as_str(), "Unhandled state packet: {:?}", packet);
+                    log::info!(
+                        target: player.username().as_str(),
+                        uuid:% = player.uuid();
+                        "Unhandled state packet: {:?}",
+                        packet
+                    );
                 }
             }
         }
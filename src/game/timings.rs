@@ -0,0 +1,222 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use drax::prelude::Uuid;
+
+pub const TICK_BUDGET: Duration = Duration::from_millis(50);
+// One minute of ticks at 20 tps.
+const WINDOW: usize = 1200;
+const PUBLISH_EVERY: usize = 20;
+
+static SUMMARIES: Mutex<BTreeMap<Uuid, TickSummary>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy)]
+pub enum TickPhase {
+    Render = 0,
+    Blocks = 1,
+    State = 2,
+    Save = 3,
+}
+
+const PHASES: [TickPhase; 4] = [
+    TickPhase::Render,
+    TickPhase::Blocks,
+    TickPhase::State,
+    TickPhase::Save,
+];
+
+impl TickPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            TickPhase::Render => "render_level",
+            TickPhase::Blocks => "BlockSystem::tick_for",
+            TickPhase::State => "GlobPlayerState::tick",
+            TickPhase::Save => "save",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TickSummary {
+    pub name: String,
+    pub mean: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    pub tps: f64,
+    pub over_budget: u64,
+}
+
+pub struct TickTimings {
+    uuid: Uuid,
+    name: String,
+    durations: VecDeque<Duration>,
+    finished_at: VecDeque<Instant>,
+    phases: [Duration; 4],
+    over_budget: u64,
+    ticks: usize,
+}
+
+impl TickTimings {
+    pub fn new(uuid: Uuid, name: String) -> Self {
+        Self {
+            uuid,
+            name,
+            durations: VecDeque::with_capacity(WINDOW),
+            finished_at: VecDeque::with_capacity(WINDOW),
+            phases: Default::default(),
+            over_budget: 0,
+            ticks: 0,
+        }
+    }
+
+    pub fn record(&mut self, phase: TickPhase, elapsed: Duration) {
+        self.phases[phase as usize] += elapsed;
+    }
+
+    pub fn finish_tick(&mut self, total: Duration) {
+        if self.durations.len() == WINDOW {
+            self.durations.pop_front();
+            self.finished_at.pop_front();
+        }
+        self.durations.push_back(total);
+        self.finished_at.push_back(Instant::now());
+        self.ticks += 1;
+
+        if total > TICK_BUDGET {
+            self.over_budget += 1;
+            let breakdown = PHASES
+                .iter()
+                .map(|phase| {
+                    format!(
+                        "{} {:.1}ms",
+                        phase.name(),
+                        self.phases[*phase as usize].as_secs_f64() * 1000.0
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            log::warn!(
+                target: self.name.as_str(),
+                uuid:% = self.uuid,
+                tick_ms = total.as_secs_f64() * 1000.0;
+                "Tick took {:.1}ms (budget {}ms): {}",
+                total.as_secs_f64() * 1000.0,
+                TICK_BUDGET.as_millis(),
+                breakdown
+            );
+        }
+        self.phases = Default::default();
+
+        if self.ticks % PUBLISH_EVERY == 0 {
+            let summary = self.summary();
+            SUMMARIES.lock().unwrap().insert(self.uuid, summary);
+        }
+    }
+
+    pub fn summary(&self) -> TickSummary {
+        let mut sorted = self.durations.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let total: Duration = sorted.iter().sum();
+        let mean = if sorted.is_empty() {
+            Duration::ZERO
+        } else {
+            total / sorted.len() as u32
+        };
+        let p99 = sorted
+            .get((sorted.len() * 99 / 100).min(sorted.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or_default();
+        let tps = match (self.finished_at.front(), self.finished_at.back()) {
+            (Some(first), Some(last)) if self.finished_at.len() > 1 => {
+                (self.finished_at.len() - 1) as f64 / (*last - *first).as_secs_f64().max(0.001)
+            }
+            _ => 20.0,
+        };
+        TickSummary {
+            name: self.name.clone(),
+            mean,
+            p99,
+            max: sorted.last().copied().unwrap_or_default(),
+            tps: tps.min(20.0),
+            over_budget: self.over_budget,
+        }
+    }
+}
+
+impl Drop for TickTimings {
+    fn drop(&mut self) {
+        SUMMARIES.lock().unwrap().remove(&self.uuid);
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub fn summaries() -> Vec<TickSummary> {
+    SUMMARIES.lock().unwrap().values().cloned().collect()
+}
+
+pub fn report_lines() -> Vec<String> {
+    let summaries = summaries();
+    if summaries.is_empty() {
+        return vec!["No active sessions.".to_string()];
+    }
+    let count = summaries.len();
+    let mean = summaries.iter().map(|s| s.mean).sum::<Duration>() / count as u32;
+    let worst_p99 = summaries.iter().map(|s| s.p99).max().unwrap_or_default();
+    let lowest_tps = summaries
+        .iter()
+        .map(|s| s.tps)
+        .fold(20.0, |lowest: f64, tps| lowest.min(tps));
+
+    let mut lines = vec![format!(
+        "{} session(s): mean tick {:.2}ms, worst p99 {:.2}ms, lowest tps {:.1}",
+        count,
+        millis(mean),
+        millis(worst_p99),
+        lowest_tps
+    )];
+    for summary in summaries {
+        lines.push(format!(
+            "  {}: {:.1} tps, mean {:.2}ms, p99 {:.2}ms, max {:.2}ms, {} over budget",
+            summary.name,
+            summary.tps,
+            millis(summary.mean),
+            millis(summary.p99),
+            millis(summary.max),
+            summary.over_budget
+        ));
+    }
+    lines
+}
+
+// Periodically logs the sessions that spent the last window behind schedule.
+pub fn spawn_lag_reporter(every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let lagging = summaries()
+                .into_iter()
+                .filter(|summary| summary.p99 > TICK_BUDGET || summary.tps < 19.0)
+                .collect::<Vec<_>>();
+            if lagging.is_empty() {
+                continue;
+            }
+            log::warn!("Lag report: {} session(s) behind schedule.", lagging.len());
+            for summary in lagging {
+                log::warn!(
+                    target: summary.name.as_str(),
+                    "{:.1} tps, mean {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+                    summary.tps,
+                    millis(summary.mean),
+                    millis(summary.p99),
+                    millis(summary.max)
+                );
+            }
+        }
+    });
+}
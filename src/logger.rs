@@ -1,11 +1,51 @@
-use std::path::PathBuf;
+use std::fmt::Arguments;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+use chrono::NaiveDate;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::kv::{self, Key, Value, VisitSource};
 use log::LevelFilter;
 
 #[derive(Debug, Clone)]
 pub struct LoggerOptions {
     pub log_level: LevelFilter,
     pub log_file: Option<PathBuf>,
+    pub rotation: RotationOptions,
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde_derive::Serialize, serde_derive::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    // One JSON object per line: timestamp, level, target, message and the record's key-values.
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct RotationOptions {
+    // Defaults to an `archive` directory next to the active log file.
+    pub archive_dir: Option<PathBuf>,
+    pub max_file_size: Option<u64>,
+    pub rotate_daily: bool,
+    pub retention: usize,
+    pub compress: bool,
+}
+
+impl Default for RotationOptions {
+    fn default() -> Self {
+        RotationOptions {
+            archive_dir: None,
+            max_file_size: Some(16 * 1024 * 1024),
+            rotate_daily: true,
+            retention: 14,
+            compress: true,
+        }
+    }
 }
 
 impl Default for LoggerOptions {
@@ -13,7 +53,44 @@ impl Default for LoggerOptions {
         LoggerOptions {
             log_level: LevelFilter::Info,
             log_file: Some(PathBuf::from("./output.log")),
+            rotation: Default::default(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+pub type ConsolePrinter = Box<dyn FnMut(String) + Send>;
+
+// While the interactive console is running, log lines are handed to it so they are drawn above
+// the line being typed instead of through it.
+static CONSOLE_PRINTER: Mutex<Option<ConsolePrinter>> = Mutex::new(None);
+
+pub fn set_console_printer(printer: Option<ConsolePrinter>) {
+    *CONSOLE_PRINTER.lock().unwrap() = printer;
+}
+
+#[derive(Default)]
+struct ConsoleOutput {
+    pending: Vec<u8>,
+}
+
+impl Write for ConsoleOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut printer = CONSOLE_PRINTER.lock().unwrap();
+        let print = match printer.as_mut() {
+            Some(print) => print,
+            None => return std::io::stdout().write(buf),
+        };
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line = self.pending.drain(..=end).collect::<Vec<_>>();
+            print(String::from_utf8_lossy(&line[..end]).into_owned());
         }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
     }
 }
 
@@ -21,29 +98,220 @@ pub fn attach_default_system_logger() -> anyhow::Result<()> {
     attach_system_logger(LoggerOptions::default())
 }
 
-pub fn attach_system_logger(options: LoggerOptions) -> anyhow::Result<()> {
-    let mut dispatcher = fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "{{{}}} [{}/{}] {}",
-                chrono::Local::now().format("%d/%m/%y %H:%M:%S"),
-                record.target(),
-                record.level(),
-                message
-            ))
+struct FieldCollector(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for FieldCollector {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_bool() {
+            serde_json::Value::from(value)
+        } else if let Some(value) = value.to_i64() {
+            serde_json::Value::from(value)
+        } else if let Some(value) = value.to_u64() {
+            serde_json::Value::from(value)
+        } else if let Some(value) = value.to_f64() {
+            serde_json::Value::from(value)
+        } else {
+            serde_json::Value::from(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+fn collect_fields(record: &log::Record) -> serde_json::Map<String, serde_json::Value> {
+    let mut collector = FieldCollector(serde_json::Map::new());
+    let _ = record.key_values().visit(&mut collector);
+    collector.0
+}
+
+fn format_text(out: fern::FormatCallback, message: &Arguments, record: &log::Record) {
+    let fields = collect_fields(record)
+        .into_iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(value) => format!(" {}={}", key, value),
+            value => format!(" {}={}", key, value),
         })
-        .level(options.log_level)
-        .chain(std::io::stdout());
+        .collect::<String>();
+    out.finish(format_args!(
+        "{{{}}} [{}/{}] {}{}",
+        chrono::Local::now().format("%d/%m/%y %H:%M:%S"),
+        record.target(),
+        record.level(),
+        message,
+        fields
+    ))
+}
+
+fn format_json(out: fern::FormatCallback, message: &Arguments, record: &log::Record) {
+    let line = serde_json::json!({
+        "timestamp": chrono::Local::now().to_rfc3339(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": message.to_string(),
+        "fields": collect_fields(record),
+    });
+    out.finish(format_args!("{}", line))
+}
+
+pub fn attach_system_logger(options: LoggerOptions) -> anyhow::Result<()> {
+    let mut dispatcher = match options.format {
+        LogFormat::Text => fern::Dispatch::new().format(format_text),
+        LogFormat::Json => fern::Dispatch::new().format(format_json),
+    }
+    .level(options.log_level)
+    .chain(Box::new(ConsoleOutput::default()) as Box<dyn Write + Send>);
 
     if let Some(path) = options.log_file.as_ref() {
+        let writer = RotatingFile::open(path.clone(), options.rotation.clone())?;
+        dispatcher = dispatcher.chain(Box::new(writer) as Box<dyn Write + Send>);
+    }
+
+    dispatcher.apply()?;
+
+    Ok(())
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    archive_dir: PathBuf,
+    options: RotationOptions,
+    file: File,
+    written: u64,
+    opened_on: NaiveDate,
+}
+
+impl RotatingFile {
+    // A log left over from the previous run (possibly a crash) is archived, never deleted.
+    fn open(path: PathBuf, options: RotationOptions) -> std::io::Result<Self> {
+        let archive_dir = options.archive_dir.clone().unwrap_or_else(|| {
+            path.parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join("archive")
+        });
+        std::fs::create_dir_all(&archive_dir)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
         if path.exists() {
-            std::fs::remove_file(path)?;
+            let last_modified = std::fs::metadata(&path)?
+                .modified()
+                .map(|time| chrono::DateTime::<chrono::Local>::from(time).date_naive())
+                .unwrap_or_else(|_| chrono::Local::now().date_naive());
+            archive(&path, &archive_dir, last_modified, &options)?;
         }
 
-        dispatcher = dispatcher.chain(fern::log_file(path)?);
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            archive_dir,
+            options,
+            written: 0,
+            opened_on: chrono::Local::now().date_naive(),
+        })
     }
 
-    dispatcher.apply()?;
+    fn needs_rotation(&self, incoming: usize) -> bool {
+        if self.written == 0 {
+            return false;
+        }
+        if self.options.rotate_daily && chrono::Local::now().date_naive() != self.opened_on {
+            return true;
+        }
+        match self.options.max_file_size {
+            Some(max) => self.written + incoming as u64 > max,
+            None => false,
+        }
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        archive(&self.path, &self.archive_dir, self.opened_on, &self.options)?;
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        self.opened_on = chrono::Local::now().date_naive();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.needs_rotation(buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn next_archive_path(archive_dir: &Path, date: NaiveDate) -> PathBuf {
+    let mut index = 1;
+    loop {
+        let name = format!("{}-{}.log", date.format("%Y-%m-%d"), index);
+        let candidate = archive_dir.join(&name);
+        if !candidate.exists() && !archive_dir.join(format!("{}.gz", name)).exists() {
+            return candidate;
+        }
+        index += 1;
+    }
+}
 
+// Moves the active log into the archive; compression and pruning happen off the logging thread.
+fn archive(
+    path: &Path,
+    archive_dir: &Path,
+    date: NaiveDate,
+    options: &RotationOptions,
+) -> std::io::Result<()> {
+    let archived = next_archive_path(archive_dir, date);
+    std::fs::rename(path, &archived)?;
+
+    let archive_dir = archive_dir.to_path_buf();
+    let compress = options.compress;
+    let retention = options.retention;
+    std::thread::spawn(move || {
+        if compress {
+            if let Err(err) = compress_file(&archived) {
+                eprintln!("Failed to compress {}: {}", archived.display(), err);
+            }
+        }
+        if let Err(err) = prune_archives(&archive_dir, retention) {
+            eprintln!("Failed to prune {}: {}", archive_dir.display(), err);
+        }
+    });
+    Ok(())
+}
+
+fn compress_file(path: &Path) -> std::io::Result<()> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+    let mut encoder = GzEncoder::new(File::create(&target)?, Compression::default());
+    std::io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    std::fs::remove_file(path)
+}
+
+fn prune_archives(archive_dir: &Path, retention: usize) -> std::io::Result<()> {
+    let mut archives = std::fs::read_dir(archive_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.ends_with(".log") || name.ends_with(".log.gz")
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect::<Vec<_>>();
+    if archives.len() <= retention {
+        return Ok(());
+    }
+    archives.sort();
+    for (_, path) in &archives[..archives.len() - retention] {
+        std::fs::remove_file(path)?;
+    }
     Ok(())
 }
@@ -0,0 +1,400 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+
+use drax::prelude::Uuid;
+use drax::throw_explain;
+use mcprotocol::clientbound::login::ClientboundLoginRegistry;
+use mcprotocol::common::{GameProfile, ProfileProperty};
+use mcprotocol::serverbound::login::ServerboundLoginRegistry;
+use rand::RngCore;
+use rsa::pkcs8::EncodePublicKey;
+use hmac::{Hmac, Mac};
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey};
+use serde_derive::Deserialize;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use shovel::client::ServerPlayer;
+use shovel::phase::login::LoginServer;
+
+use crate::config::LoginConfig;
+
+// Matches the vanilla server's `UUID.nameUUIDFromBytes("OfflinePlayer:" + name)` so offline
+// saves line up with what a vanilla offline server would produce.
+pub fn offline_uuid(name: &str) -> Uuid {
+    let mut bytes = md5::compute(format!("OfflinePlayer:{}", name)).0;
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    Uuid::from_bytes(bytes)
+}
+
+async fn read_hello(player: &mut ServerPlayer) -> drax::prelude::Result<String> {
+    match player.reader.read_login_packet().await? {
+        ServerboundLoginRegistry::Hello { name, .. } => {
+            if name.is_empty()
+                || name.len() > 16
+                || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                throw_explain!(format!("Invalid username {:?}", name));
+            }
+            Ok(name)
+        }
+        packet => throw_explain!(format!("Expected login hello, got {:?}", packet)),
+    }
+}
+
+pub struct OfflineLoginServer;
+
+impl LoginServer for OfflineLoginServer {
+    async fn login(player: &mut ServerPlayer) -> drax::prelude::Result<GameProfile> {
+        let name = read_hello(player).await?;
+        log::info!("Offline login for {}.", name);
+        Ok(GameProfile {
+            id: offline_uuid(&name),
+            name,
+            properties: vec![],
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct SessionProperty {
+    name: String,
+    value: String,
+    signature: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionProfile {
+    id: String,
+    name: String,
+    #[serde(default)]
+    properties: Vec<SessionProperty>,
+}
+
+// Minecraft's server hash is a sha1 digest rendered as a signed two's complement hex number.
+fn minecraft_digest(shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(shared_secret);
+    hasher.update(public_key);
+    let mut digest: [u8; 20] = hasher.finalize().into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            *byte = !*byte;
+            if carry {
+                let (next, overflow) = byte.overflowing_add(1);
+                *byte = next;
+                carry = overflow;
+            }
+        }
+    }
+    let hex = digest
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let hex = hex.trim_start_matches('0');
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_string()
+    }
+}
+
+async fn has_joined(
+    session_server: &str,
+    name: &str,
+    server_hash: &str,
+) -> anyhow::Result<Option<SessionProfile>> {
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/session/minecraft/hasJoined",
+            session_server.trim_end_matches('/')
+        ))
+        .query(&[("username", name), ("serverId", server_hash)])
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NO_CONTENT {
+        return Ok(None);
+    }
+    Ok(Some(response.error_for_status()?.json().await?))
+}
+
+// One keypair for the server's lifetime, like vanilla; generating one per login would let any
+// client burn CPU on the executor just by saying hello. Each login still gets a fresh challenge.
+struct LoginKey {
+    private_key: RsaPrivateKey,
+    public_key: Vec<u8>,
+}
+
+static LOGIN_KEY: OnceLock<LoginKey> = OnceLock::new();
+
+// Called once at startup when online login is configured.
+pub fn init_login_key() -> anyhow::Result<()> {
+    let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)
+        .map_err(|err| anyhow::anyhow!("Failed to generate login key: {}", err))?;
+    let public_key = private_key
+        .to_public_key()
+        .to_public_key_der()
+        .map_err(|err| anyhow::anyhow!("Failed to encode login key: {}", err))?
+        .as_bytes()
+        .to_vec();
+    if LOGIN_KEY
+        .set(LoginKey {
+            private_key,
+            public_key,
+        })
+        .is_err()
+    {
+        anyhow::bail!("Login key initialized twice");
+    }
+    Ok(())
+}
+
+pub struct OnlineLoginServer;
+
+impl LoginServer for OnlineLoginServer {
+    async fn login(player: &mut ServerPlayer) -> drax::prelude::Result<GameProfile> {
+        let session_server = match &crate::config::get().login {
+            LoginConfig::Online { session_server } => session_server.as_str(),
+            _ => throw_explain!("Online login server used without online login config."),
+        };
+
+        let name = read_hello(player).await?;
+
+        let LoginKey {
+            private_key,
+            public_key,
+        } = match LOGIN_KEY.get() {
+            Some(key) => key,
+            None => throw_explain!("Online login used before the login key was generated."),
+        };
+        let mut challenge = vec![0u8; 4];
+        rand::thread_rng().fill_bytes(&mut challenge);
+
+        player
+            .writer
+            .write_login_packet(&ClientboundLoginRegistry::Hello {
+                server_id: String::new(),
+                public_key: public_key.clone(),
+                challenge: challenge.clone(),
+            })
+            .await?;
+
+        let (key_bytes, encrypted_challenge) = match player.reader.read_login_packet().await? {
+            ServerboundLoginRegistry::Key {
+                key_bytes,
+                encrypted_challenge,
+            } => (key_bytes, encrypted_challenge),
+            packet => throw_explain!(format!("Expected encryption key, got {:?}", packet)),
+        };
+
+        let decrypted_challenge = private_key.decrypt(Pkcs1v15Encrypt, &encrypted_challenge);
+        if decrypted_challenge.ok().as_deref() != Some(challenge.as_slice()) {
+            throw_explain!(format!("Challenge mismatch during login of {}", name));
+        }
+        let shared_secret = match private_key.decrypt(Pkcs1v15Encrypt, &key_bytes) {
+            Ok(secret) if secret.len() == 16 => secret,
+            _ => throw_explain!(format!("Invalid shared secret during login of {}", name)),
+        };
+
+        player.enable_encryption(&shared_secret);
+
+        let server_hash = minecraft_digest(&shared_secret, public_key);
+        let profile = match has_joined(session_server, &name, &server_hash).await {
+            Ok(Some(profile)) => profile,
+            Ok(None) => throw_explain!(format!("{} failed session verification", name)),
+            Err(err) => throw_explain!(format!(
+                "Session server {} unreachable while verifying {}: {}",
+                session_server, name, err
+            )),
+        };
+        let id = match Uuid::parse_str(&profile.id) {
+            Ok(id) => id,
+            Err(_) => throw_explain!(format!("Session server returned bad id {}", profile.id)),
+        };
+
+        log::info!("Verified online login for {} ({}).", profile.name, id);
+        Ok(GameProfile {
+            id,
+            name: profile.name,
+            properties: profile
+                .properties
+                .into_iter()
+                .map(|property| ProfileProperty {
+                    name: property.name,
+                    value: property.value,
+                    signature: property.signature,
+                })
+                .collect(),
+        })
+    }
+}
+
+const VELOCITY_CHANNEL: &'static str = "velocity:player_info";
+const VELOCITY_MODERN_DEFAULT: u8 = 1;
+
+struct ForwardingReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ForwardingReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(taken)
+    }
+
+    fn var_int(&mut self) -> Option<i32> {
+        let mut value = 0i32;
+        for position in 0..5 {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as i32) << (position * 7);
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.var_int()?;
+        if len < 0 {
+            return None;
+        }
+        String::from_utf8(self.take(len as usize)?.to_vec()).ok()
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        Some(self.take(1)?[0] != 0)
+    }
+
+    fn uuid(&mut self) -> Option<Uuid> {
+        Uuid::from_slice(self.take(16)?).ok()
+    }
+}
+
+struct ForwardedPlayer {
+    address: String,
+    profile: GameProfile,
+}
+
+fn read_forwarded_player(payload: &[u8]) -> Option<ForwardedPlayer> {
+    let mut reader = ForwardingReader { data: payload };
+    let version = reader.var_int()?;
+    if version < VELOCITY_MODERN_DEFAULT as i32 {
+        return None;
+    }
+    let address = reader.string()?;
+    let id = reader.uuid()?;
+    let name = reader.string()?;
+    let property_count = reader.var_int()?;
+    let mut properties = Vec::with_capacity(property_count.max(0) as usize);
+    for _ in 0..property_count {
+        let name = reader.string()?;
+        let value = reader.string()?;
+        let signature = if reader.bool()? {
+            Some(reader.string()?)
+        } else {
+            None
+        };
+        properties.push(ProfileProperty {
+            name,
+            value,
+            signature,
+        });
+    }
+    Some(ForwardedPlayer {
+        address,
+        profile: GameProfile {
+            id,
+            name,
+            properties,
+        },
+    })
+}
+
+// Velocity signs the forwarded payload with HMAC-SHA256 over the shared secret; the 32 byte
+// signature prefixes the payload.
+fn verify_forwarding(secret: &[u8], data: &[u8]) -> Option<&[u8]> {
+    if data.len() < 32 {
+        return None;
+    }
+    let (signature, payload) = data.split_at(32);
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).ok()?;
+    mac.update(payload);
+    mac.verify_slice(signature).ok()?;
+    Some(payload)
+}
+
+pub struct VelocityLoginServer;
+
+impl LoginServer for VelocityLoginServer {
+    async fn login(player: &mut ServerPlayer) -> drax::prelude::Result<GameProfile> {
+        let secret = match &crate::config::get().login {
+            LoginConfig::Velocity { secret } => secret.as_bytes(),
+            _ => throw_explain!("Velocity login server used without velocity login config."),
+        };
+
+        let name = read_hello(player).await?;
+
+        let transaction_id = rand::random::<u16>() as i32;
+        player
+            .writer
+            .write_login_packet(&ClientboundLoginRegistry::CustomQuery {
+                transaction_id,
+                identifier: VELOCITY_CHANNEL.to_string(),
+                data: vec![VELOCITY_MODERN_DEFAULT],
+            })
+            .await?;
+
+        let data = match player.reader.read_login_packet().await? {
+            ServerboundLoginRegistry::CustomQueryAnswer {
+                transaction_id: answer_id,
+                data,
+            } if answer_id == transaction_id => match data {
+                Some(data) => data,
+                None => throw_explain!(format!(
+                    "{} connected directly; this server only accepts players from Velocity",
+                    name
+                )),
+            },
+            packet => throw_explain!(format!("Expected velocity forwarding, got {:?}", packet)),
+        };
+
+        let payload = match verify_forwarding(secret, &data) {
+            Some(payload) => payload,
+            None => throw_explain!(format!(
+                "Invalid velocity forwarding signature for {}; check the forwarding secret",
+                name
+            )),
+        };
+        let forwarded = match read_forwarded_player(payload) {
+            Some(forwarded) => forwarded,
+            None => throw_explain!(format!("Malformed velocity forwarding data for {}", name)),
+        };
+
+        // The connection comes from the proxy; from here on the player's address is the one
+        // Velocity saw, keeping the proxy's source port.
+        let ip = match forwarded.address.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => throw_explain!(format!(
+                "Invalid forwarded address {:?} for {}",
+                forwarded.address, name
+            )),
+        };
+        player.address = SocketAddr::new(ip, player.address.port());
+
+        log::info!(
+            "Velocity forwarded login for {} ({}) from {}.",
+            forwarded.profile.name,
+            forwarded.profile.id,
+            player.address
+        );
+        Ok(forwarded.profile)
+    }
+}
@@ -1,10 +1,11 @@
 #![feature(async_fn_in_trait)]
 #![feature(once_cell)]
 
+use std::time::Duration;
+
 use drax::prelude::ErrorType;
-use log::LevelFilter;
-use mcprotocol::clientbound::play::ClientboundPlayRegistry::PlayerAbilities;
-use mcprotocol::common::play::{GameType, Location, SimpleLocation};
+use mcprotocol::clientbound::play::ClientboundPlayRegistry::{Disconnect, PlayerAbilities};
+use mcprotocol::common::play::GameType;
 use mcprotocol::{combine, msg};
 use shovel::client::ProcessedPlayer;
 use shovel::entity::tracking::TrackableEntity;
@@ -17,32 +18,95 @@ use tokio::join;
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::chat::{create_global_chat_handle, ChatHandlerEntityStub, ChatHandlerPacket};
+use crate::config::LoginConfig;
 use crate::console::{attach_console, ConsoleHandle};
+use crate::db::SavePacket;
 use crate::game::{ClientRouting, GameFactory};
-use crate::logger::LoggerOptions;
+use crate::login::{OfflineLoginServer, OnlineLoginServer, VelocityLoginServer};
+use crate::shutdown::{create_shutdown_handles, listen_for_signals, ShutdownListener};
 
+mod audit;
 mod chat;
+mod commands;
+mod config;
 mod console;
 mod db;
 mod game;
 mod logger;
+mod login;
+mod metrics;
 mod ranks;
 pub mod raytrace;
+mod rcon;
+mod scheduler;
+mod shutdown;
 
 fn main() {
-    db::ensure_db();
+    let args = match config::parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            std::process::exit(2);
+        }
+    };
+    let config = match config::load(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Failed to load server configuration: {:#}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if args.check_data {
+        std::process::exit(game::registry::run_data_check());
+    }
 
-    logger::attach_system_logger(LoggerOptions {
-        log_level: LevelFilter::Info,
-        log_file: None,
-    })
-    .unwrap();
+    if let Err(err) = db::ensure_db() {
+        eprintln!("Failed to open player storage: {:#}", err);
+        std::process::exit(1);
+    }
+
+    let mut logger_options = config.logging.options();
+    if args.data_tool.is_some() {
+        // Tool runs stay out of the server's log file and never rotate it.
+        logger_options.log_file = None;
+    }
+    if let Err(err) = logger::attach_system_logger(logger_options) {
+        eprintln!("Failed to attach system logger: {:#}", err);
+        std::process::exit(1);
+    }
 
     log::info!("System logger attached.");
+    // Storage is opened before the logger, so its choice is only reported now.
+    log::info!("Using {} player storage.", db::storage().name());
+
+    if let Err(err) = game::registry::ensure_registries().and_then(|_| game::registry::reload_all()) {
+        log::error!("Failed to load registries: {:#}", err);
+        log::logger().flush();
+        std::process::exit(1);
+    }
+
+    if let Err(err) = db::names::ensure_index() {
+        log::error!("Failed to build the player name index: {:#}", err);
+    }
+
+    if let Some(tool) = &args.data_tool {
+        let code = db::bundle::run_tool(tool);
+        log::logger().flush();
+        std::process::exit(code);
+    }
+
+    if let LoginConfig::Online { .. } = &config.login {
+        if let Err(err) = login::init_login_key() {
+            log::error!("{:#}", err);
+            log::logger().flush();
+            std::process::exit(1);
+        }
+    }
 
     // game factory
     log::info!("Bootstrapping game factory.");
-    let (factory_sender, _) = GameFactory::bootstrap(());
+    let (factory_sender, _) = GameFactory::bootstrap(config.spawn.location());
 
     log::info!("Creating network runtime.");
     tokio::runtime::Builder::new_multi_thread()
@@ -51,36 +115,67 @@ fn main() {
         .build()
         .unwrap()
         .block_on(async move {
-            let console = attach_console();
-            let chat = create_global_chat_handle();
-
-            if let Err(err) = spawn_server! {
-                (console, factory_sender, chat), MinehutLoginServer,
-                @proxy_protocol true,
-                @bind "0.0.0.0:25575",
-                @mc_status |count| status_builder! {
-                    description: combine!(
-                        msg!("Idle Game!\n", "#ffbbbb").bold(true),
-                        msg!("Used For showing off ScrapyardRs", "#bbbbff").italic(true)
-                    ).into(),
-                    max: count + 1,
-                    online: count,
-                },
-                @initial_location Location {
-                    inner_loc: SimpleLocation {
-                        x: 8.0,
-                        y: 1.0,
-                        z: 8.0,
-                    },
-                    yaw: 0.0,
-                    pitch: 0.0,
-                },
-                @chunk_radius 8,
-                ctx, client -> {
-                    acquire_client(ctx, client).await?;
-                    Ok(())
-                }
-            } {
+            let (shutdown_trigger, shutdown, shutdown_coordinator) = create_shutdown_handles();
+            let saves = db::create_save_worker();
+            let (commands, command_requests) = tokio::sync::mpsc::unbounded_channel();
+            let chat = create_global_chat_handle(commands.clone());
+            let console = attach_console(
+                command_requests,
+                shutdown_trigger.clone(),
+                saves.clone(),
+                chat.clone(),
+            );
+            shutdown_coordinator.spawn(chat.clone(), saves.clone(), config.shutdown.clone());
+            listen_for_signals(shutdown_trigger);
+            if config.snapshots.enabled {
+                db::snapshots::spawn_snapshotter(saves.clone(), config.snapshots.clone());
+            }
+            game::timings::spawn_lag_reporter(Duration::from_secs(60));
+            if config.metrics.enabled {
+                metrics::attach_metrics_endpoint(config.metrics.bind.clone()).await;
+            }
+            if config.rcon.enabled {
+                rcon::attach_rcon(config.rcon.clone(), commands.clone()).await;
+            }
+            scheduler::spawn_scheduler(commands, config.scripts.clone());
+            if config.registries.watch {
+                game::registry::watch_registries(Duration::from_secs(
+                    config.registries.watch_interval_secs,
+                ));
+            }
+
+            macro_rules! serve {
+                ($login:ident) => {
+                    spawn_server! {
+                        (console, factory_sender, chat, shutdown, saves), $login,
+                        @proxy_protocol config.proxy_protocol,
+                        @bind config.bind.as_str(),
+                        @mc_status |count| status_builder! {
+                            description: combine!(
+                                msg!(config.motd.title.clone(), config.motd.title_color.as_str()).bold(true),
+                                msg!(config.motd.subtitle.clone(), config.motd.subtitle_color.as_str()).italic(true)
+                            ).into(),
+                            max: count + 1,
+                            online: count,
+                        },
+                        @initial_location config.spawn.location(),
+                        @chunk_radius config.chunk_radius,
+                        ctx, client -> {
+                            acquire_client(ctx, client).await?;
+                            Ok(())
+                        }
+                    }
+                };
+            }
+
+            log::info!("Starting server on {}.", config.bind);
+            let result = match &config.login {
+                LoginConfig::Minehut => serve!(MinehutLoginServer),
+                LoginConfig::Offline => serve!(OfflineLoginServer),
+                LoginConfig::Online { .. } => serve!(OnlineLoginServer),
+                LoginConfig::Velocity { .. } => serve!(VelocityLoginServer),
+            };
+            if let Err(err) = result {
                 if !matches!(err.error_type, ErrorType::EOF) {
                     log::error!("Error running server: {}", err);
                 }
@@ -89,13 +184,26 @@ fn main() {
 }
 
 async fn acquire_client(
-    (console, factory_sender, chat): (
+    (console, factory_sender, chat, shutdown, saves): (
         UnboundedSender<ConsoleHandle>,
         UnboundedSender<(ClientRouting, ConnectedPlayer)>,
         UnboundedSender<ChatHandlerPacket>,
+        ShutdownListener,
+        UnboundedSender<SavePacket>,
     ),
     mut client: ProcessedPlayer,
 ) -> drax::prelude::Result<()> {
+    if shutdown.is_stopping() {
+        client
+            .server_player
+            .writer
+            .write_play_packet(&Disconnect {
+                reason: msg!("Server is shutting down.", "red").into(),
+            })
+            .await?;
+        return Ok(());
+    }
+
     client
         .send_client_login(
             "Idle Game",
@@ -103,7 +211,7 @@ async fn acquire_client(
                 hardcore: false,
                 game_type: GameType::Survival,
                 seed: 0,
-                max_players: 20,
+                max_players: config::get().max_players,
                 simulation_distance: 20,
                 reduced_debug_info: false,
                 show_death_screen: false,
@@ -158,6 +266,8 @@ async fn acquire_client(
             ClientRouting {
                 chat,
                 console: console_rx,
+                shutdown,
+                saves,
             },
             client,
         ))
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const TICK_BUCKETS: &'static [f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25];
+const SAVE_BUCKETS: &'static [f64] = &[0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    const fn new() -> Self {
+        Self(AtomicI64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct LabeledCounter {
+    label: &'static str,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl LabeledCounter {
+    const fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc<L: ToString>(&self, label: L) {
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .entry(label.to_string())
+            .or_insert(0) += 1;
+    }
+}
+
+struct HistogramState {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+pub struct Histogram {
+    buckets: &'static [f64],
+    state: Mutex<HistogramState>,
+}
+
+impl Histogram {
+    const fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            state: Mutex::new(HistogramState {
+                counts: Vec::new(),
+                sum: 0.0,
+                count: 0,
+            }),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut state = self.state.lock().unwrap();
+        if state.counts.is_empty() {
+            state.counts = vec![0; self.buckets.len()];
+        }
+        for (index, bound) in self.buckets.iter().enumerate() {
+            if seconds <= *bound {
+                state.counts[index] += 1;
+            }
+        }
+        state.sum += seconds;
+        state.count += 1;
+    }
+}
+
+pub struct Metrics {
+    pub players_online: Gauge,
+    pub logins: Counter,
+    pub disconnects: Counter,
+    pub session_tick: Histogram,
+    pub missed_ticks: Counter,
+    pub chat_broadcasts: Counter,
+    pub blocks_broken: LabeledCounter,
+    pub db_save: Histogram,
+    pub db_save_failures: Counter,
+    pub console_commands: Counter,
+}
+
+pub static METRICS: Metrics = Metrics {
+    players_online: Gauge::new(),
+    logins: Counter::new(),
+    disconnects: Counter::new(),
+    session_tick: Histogram::new(TICK_BUCKETS),
+    missed_ticks: Counter::new(),
+    chat_broadcasts: Counter::new(),
+    blocks_broken: LabeledCounter::new("ordinal"),
+    db_save: Histogram::new(SAVE_BUCKETS),
+    db_save_failures: Counter::new(),
+    console_commands: Counter::new(),
+};
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    write_header(out, name, "counter", help);
+    let _ = writeln!(out, "{} {}", name, counter.get());
+}
+
+fn write_labeled_counter(out: &mut String, name: &str, help: &str, counter: &LabeledCounter) {
+    write_header(out, name, "counter", help);
+    for (label, value) in counter.values.lock().unwrap().iter() {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, counter.label, label, value);
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    write_header(out, name, "histogram", help);
+    let state = histogram.state.lock().unwrap();
+    for (index, bound) in histogram.buckets.iter().enumerate() {
+        let count = state.counts.get(index).copied().unwrap_or(0);
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+    }
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, state.count);
+    let _ = writeln!(out, "{}_sum {}", name, state.sum);
+    let _ = writeln!(out, "{}_count {}", name, state.count);
+}
+
+impl Metrics {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_header(
+            &mut out,
+            "idle_players_online",
+            "gauge",
+            "Players with an active game session.",
+        );
+        let _ = writeln!(out, "idle_players_online {}", self.players_online.get());
+        write_counter(
+            &mut out,
+            "idle_logins_total",
+            "Game sessions started.",
+            &self.logins,
+        );
+        write_counter(
+            &mut out,
+            "idle_disconnects_total",
+            "Game sessions ended.",
+            &self.disconnects,
+        );
+        write_histogram(
+            &mut out,
+            "idle_session_tick_seconds",
+            "Time spent in a single game session tick.",
+            &self.session_tick,
+        );
+        write_counter(
+            &mut out,
+            "idle_missed_ticks_total",
+            "Session ticks that started later than their 50ms slot.",
+            &self.missed_ticks,
+        );
+        write_counter(
+            &mut out,
+            "idle_chat_broadcasts_total",
+            "Chat messages broadcast by the chat handler.",
+            &self.chat_broadcasts,
+        );
+        write_labeled_counter(
+            &mut out,
+            "idle_blocks_broken_total",
+            "Blocks broken by players, by block ordinal.",
+            &self.blocks_broken,
+        );
+        write_histogram(
+            &mut out,
+            "idle_db_save_seconds",
+            "Time spent writing a player save.",
+            &self.db_save,
+        );
+        write_counter(
+            &mut out,
+            "idle_db_save_failures_total",
+            "Player saves that failed.",
+            &self.db_save_failures,
+        );
+        write_counter(
+            &mut out,
+            "idle_console_commands_total",
+            "Console commands executed.",
+            &self.console_commands,
+        );
+        out
+    }
+}
+
+async fn serve_connection(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || request.len() > 8192 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let (status, content_type, body) = if request_line.starts_with("GET /metrics ") {
+        (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            METRICS.render(),
+        )
+    } else {
+        ("404 Not Found", "text/plain", "not found\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+pub async fn attach_metrics_endpoint(bind: String) {
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("Failed to bind metrics endpoint on {}: {}", bind, err);
+            return;
+        }
+    };
+    log::info!("Serving metrics on http://{}/metrics", bind);
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    log::warn!("Metrics endpoint accept failed: {}", err);
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                let _ = serve_connection(stream).await;
+            });
+        }
+    });
+}
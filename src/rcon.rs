@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::audit::AuditSource;
+use crate::commands::CommandRequest;
+use crate::config::RconConfig;
+use crate::ranks::Rank;
+
+// Packet types from the Source RCON protocol, which is what Minecraft's RCON speaks.
+const RESPONSE_VALUE: i32 = 0;
+const EXEC_COMMAND: i32 = 2;
+const AUTH_RESPONSE: i32 = 2;
+const AUTH: i32 = 3;
+
+// id, type and the two trailing nulls.
+const HEADER_LEN: usize = 10;
+const MAX_REQUEST_LEN: usize = 1460;
+const MAX_RESPONSE_BODY: usize = 4096;
+// Slows down password guessing; the connection is closed after each failure.
+const AUTH_FAILURE_DELAY: Duration = Duration::from_secs(2);
+// Unauthenticated clients are dropped if they go quiet for this long.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_CONNECTIONS_PER_ADDRESS: usize = 4;
+// An address with this many failed logins inside the window is refused until they age out.
+const MAX_AUTH_FAILURES: usize = 5;
+const AUTH_FAILURE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+// Open connections and recent failed logins per address, shared by every connection.
+#[derive(Default)]
+struct Limits {
+    connections: HashMap<IpAddr, usize>,
+    failures: HashMap<IpAddr, Vec<Instant>>,
+}
+
+type SharedLimits = Arc<Mutex<Limits>>;
+
+impl Limits {
+    fn admit(&mut self, ip: IpAddr) -> Result<(), &'static str> {
+        if let Some(failures) = self.failures.get_mut(&ip) {
+            failures.retain(|at| at.elapsed() < AUTH_FAILURE_WINDOW);
+            if failures.len() >= MAX_AUTH_FAILURES {
+                return Err("too many failed logins");
+            }
+            if failures.is_empty() {
+                self.failures.remove(&ip);
+            }
+        }
+        let connections = self.connections.entry(ip).or_default();
+        if *connections >= MAX_CONNECTIONS_PER_ADDRESS {
+            return Err("too many open connections");
+        }
+        *connections += 1;
+        Ok(())
+    }
+
+    fn release(&mut self, ip: IpAddr) {
+        if let Some(connections) = self.connections.get_mut(&ip) {
+            *connections -= 1;
+            if *connections == 0 {
+                self.connections.remove(&ip);
+            }
+        }
+    }
+
+    fn record_failure(&mut self, ip: IpAddr) {
+        self.failures.entry(ip).or_default().push(Instant::now());
+    }
+}
+
+struct RconPacket {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+async fn read_packet(stream: &mut TcpStream) -> std::io::Result<Option<RconPacket>> {
+    let len = match stream.read_i32_le().await {
+        Ok(len) => len as usize,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if !(HEADER_LEN..=MAX_REQUEST_LEN).contains(&len) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("packet length {} out of range", len),
+        ));
+    }
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).await?;
+    let id = i32::from_le_bytes(buf[0..4].try_into().unwrap());
+    let kind = i32::from_le_bytes(buf[4..8].try_into().unwrap());
+    let body = &buf[8..len - 2];
+    let body = String::from_utf8_lossy(body).trim_end_matches('\0').to_string();
+    Ok(Some(RconPacket { id, kind, body }))
+}
+
+async fn write_packet(
+    stream: &mut TcpStream,
+    id: i32,
+    kind: i32,
+    body: &str,
+) -> std::io::Result<()> {
+    let mut buf = Vec::with_capacity(body.len() + HEADER_LEN + 4);
+    buf.extend_from_slice(&((body.len() + HEADER_LEN) as i32).to_le_bytes());
+    buf.extend_from_slice(&id.to_le_bytes());
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(body.as_bytes());
+    buf.extend_from_slice(&[0, 0]);
+    stream.write_all(&buf).await
+}
+
+// Long output is split over several packets with the same id, on char boundaries.
+async fn write_response(stream: &mut TcpStream, id: i32, body: &str) -> std::io::Result<()> {
+    let mut rest = body;
+    loop {
+        let mut end = rest.len().min(MAX_RESPONSE_BODY);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        write_packet(stream, id, RESPONSE_VALUE, &rest[..end]).await?;
+        rest = &rest[end..];
+        if rest.is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+fn password_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn serve_connection(
+    mut stream: TcpStream,
+    address: SocketAddr,
+    password: &str,
+    commands: &UnboundedSender<CommandRequest>,
+    limits: &SharedLimits,
+) -> std::io::Result<()> {
+    let mut authenticated = false;
+    loop {
+        let packet = if authenticated {
+            read_packet(&mut stream).await?
+        } else {
+            match tokio::time::timeout(AUTH_TIMEOUT, read_packet(&mut stream)).await {
+                Ok(packet) => packet?,
+                Err(_) => {
+                    log::warn!("RCON client {} did not authenticate in time.", address);
+                    return Ok(());
+                }
+            }
+        };
+        let packet = match packet {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+        match packet.kind {
+            AUTH => {
+                if password_matches(&packet.body, password) {
+                    authenticated = true;
+                    log::info!("RCON client {} authenticated.", address);
+                    write_packet(&mut stream, packet.id, AUTH_RESPONSE, "").await?;
+                } else {
+                    log::warn!("RCON client {} failed to authenticate.", address);
+                    limits.lock().unwrap().record_failure(address.ip());
+                    tokio::time::sleep(AUTH_FAILURE_DELAY).await;
+                    write_packet(&mut stream, -1, AUTH_RESPONSE, "").await?;
+                    return Ok(());
+                }
+            }
+            _ if !authenticated => {
+                log::warn!("RCON client {} sent a request before authenticating.", address);
+                return Ok(());
+            }
+            EXEC_COMMAND => {
+                let (reply, output) = tokio::sync::oneshot::channel();
+                let request = CommandRequest {
+                    line: packet.body,
+                    source: AuditSource::Remote {
+                        address: address.to_string(),
+                    },
+                    rank: Rank::Owner,
+                    reply: Some(reply),
+                };
+                let body = match commands.send(request) {
+                    Ok(()) => output.await.unwrap_or_default().join("\n"),
+                    Err(_) => "Commands are unavailable right now.".to_string(),
+                };
+                write_response(&mut stream, packet.id, &body).await?;
+            }
+            // Clients send an empty response packet after a command and wait for it to be echoed,
+            // to know where a split response ends.
+            RESPONSE_VALUE => write_packet(&mut stream, packet.id, RESPONSE_VALUE, "").await?,
+            kind => {
+                let body = format!("Unknown request {:x}", kind);
+                write_packet(&mut stream, packet.id, RESPONSE_VALUE, &body).await?;
+            }
+        }
+    }
+}
+
+pub async fn attach_rcon(config: RconConfig, commands: UnboundedSender<CommandRequest>) {
+    let listener = match TcpListener::bind(&config.bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("Failed to bind RCON on {}: {}", config.bind, err);
+            return;
+        }
+    };
+    log::info!("Listening for RCON on {}", config.bind);
+    let limits = SharedLimits::default();
+    tokio::spawn(async move {
+        loop {
+            let (stream, address) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    log::warn!("RCON accept failed: {}", err);
+                    continue;
+                }
+            };
+            if let Err(reason) = limits.lock().unwrap().admit(address.ip()) {
+                log::warn!("Refusing RCON client {}: {}.", address, reason);
+                continue;
+            }
+            let password = config.password.clone();
+            let commands = commands.clone();
+            let limits = limits.clone();
+            tokio::spawn(async move {
+                let served = serve_connection(stream, address, &password, &commands, &limits);
+                if let Err(err) = served.await {
+                    log::warn!("RCON connection from {} failed: {}", address, err);
+                }
+                limits.lock().unwrap().release(address.ip());
+            });
+        }
+    });
+}
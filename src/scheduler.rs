@@ -0,0 +1,274 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Timelike};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::audit::AuditSource;
+use crate::commands::CommandRequest;
+use crate::config::ScriptsConfig;
+use crate::ranks::Rank;
+
+static JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
+// A standard five-field cron expression (minute, hour, day of month, month, day of week) in
+// local time. Fields take `*`, numbers, ranges, lists and `/` steps; `@hourly`, `@daily`,
+// `@weekly` and `@monthly` are accepted too.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Like cron: when both day fields are restricted, either one matching is enough.
+    any_day: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step in `{}`", part)),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let parse = |value: &str| {
+                    value
+                        .parse::<u32>()
+                        .map_err(|_| format!("`{}` is not a number", value))
+                };
+                let (start, end) = (parse(start)?, parse(end)?);
+                // `5/15` means every 15 starting at 5.
+                if part.contains('/') && !range.contains('-') {
+                    (start, max)
+                } else {
+                    (start, end)
+                }
+            }
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("`{}` is outside {}-{}", part, min, max));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        };
+        // Sunday is both 0 and 7.
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day != "*" && weekday != "*",
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        let day = if self.any_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        self.months & (1 << date.month()) != 0 && day
+    }
+
+    pub fn matches(&self, at: DateTime<Local>) -> bool {
+        self.matches_day(at.date_naive())
+            && self.hours & (1 << at.hour()) != 0
+            && self.minutes & (1 << at.minute()) != 0
+    }
+
+    // The first matching minute after `after`, looking at most about four years ahead.
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local() + Duration::minutes(1);
+        let start = start.date().and_hms_opt(start.hour(), start.minute(), 0)?;
+        for day in 0..366 * 4 + 1 {
+            let date = start.date() + Duration::days(day);
+            if !self.matches_day(date) {
+                continue;
+            }
+            for hour in (0..24).filter(|hour| self.hours & (1 << hour) != 0) {
+                for minute in (0..60).filter(|minute| self.minutes & (1 << minute) != 0) {
+                    let candidate = date.and_hms_opt(hour, minute, 0)?;
+                    if candidate < start {
+                        continue;
+                    }
+                    // Times skipped by a DST change never happen.
+                    if let Some(at) = Local.from_local_datetime(&candidate).earliest() {
+                        return Some(at);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+struct Job {
+    id: usize,
+    name: String,
+    expression: String,
+    schedule: CronSchedule,
+    command: String,
+    runs: u64,
+}
+
+pub struct JobSummary {
+    pub id: usize,
+    pub name: String,
+    pub expression: String,
+    pub command: String,
+    pub runs: u64,
+    pub next_run: Option<DateTime<Local>>,
+}
+
+pub fn jobs() -> Vec<JobSummary> {
+    let now = Local::now();
+    JOBS.lock()
+        .unwrap()
+        .iter()
+        .map(|job| JobSummary {
+            id: job.id,
+            name: job.name.clone(),
+            expression: job.expression.clone(),
+            command: job.command.clone(),
+            runs: job.runs,
+            next_run: job.schedule.next_after(now),
+        })
+        .collect()
+}
+
+// By id or name; cancelled jobs come back on the next restart unless removed from the config.
+pub fn cancel(job: &str) -> Option<String> {
+    let mut jobs = JOBS.lock().unwrap();
+    let index = jobs.iter().position(|candidate| {
+        candidate.name.eq_ignore_ascii_case(job) || candidate.id.to_string() == job
+    })?;
+    Some(jobs.remove(index).name)
+}
+
+// Runs one command through the console and logs its output under `name`.
+async fn run_command(commands: &UnboundedSender<CommandRequest>, name: &str, line: String) {
+    let (reply, output) = tokio::sync::oneshot::channel();
+    let request = CommandRequest {
+        line,
+        source: AuditSource::Script {
+            name: name.to_string(),
+        },
+        rank: Rank::Owner,
+        reply: Some(reply),
+    };
+    if commands.send(request).is_err() {
+        log::error!(target: name, "The console is not running.");
+        return;
+    }
+    for line in output.await.unwrap_or_default() {
+        log::info!(target: name, "{}", line);
+    }
+}
+
+// Each command finishes before the next one starts. Blank lines and `#` comments are skipped.
+pub async fn run_script(commands: &UnboundedSender<CommandRequest>, path: &Path) {
+    let script = match std::fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(err) => {
+            log::error!("Failed to read script {}: {}", path.display(), err);
+            return;
+        }
+    };
+    log::info!("Running script {}.", path.display());
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "script".to_string());
+    for line in script.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        run_command(commands, &name, line.to_string()).await;
+    }
+}
+
+pub fn spawn_scheduler(commands: UnboundedSender<CommandRequest>, config: ScriptsConfig) {
+    {
+        let mut jobs = JOBS.lock().unwrap();
+        for (id, job) in config.jobs.iter().enumerate() {
+            // Validated with the rest of the config.
+            let schedule = CronSchedule::parse(&job.schedule).unwrap();
+            jobs.push(Job {
+                id: id + 1,
+                name: job.name.clone(),
+                expression: job.schedule.clone(),
+                schedule,
+                command: job.command.clone(),
+                runs: 0,
+            });
+        }
+    }
+
+    tokio::spawn(async move {
+        if let Some(startup) = &config.startup {
+            run_script(&commands, &crate::config::get().data_dir.join(startup)).await;
+        }
+        if config.jobs.is_empty() {
+            return;
+        }
+        loop {
+            // Wake on each minute boundary and run whatever is due for it.
+            let now = Local::now();
+            let minute = now.date_naive().and_hms_opt(now.hour(), now.minute(), 0).unwrap();
+            let next = match Local.from_local_datetime(&(minute + Duration::minutes(1))).earliest()
+            {
+                Some(next) => next,
+                None => now + Duration::minutes(1),
+            };
+            let wait = (next - now).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            let due = {
+                let mut jobs = JOBS.lock().unwrap();
+                jobs.iter_mut()
+                    .filter(|job| job.schedule.matches(next))
+                    .map(|job| {
+                        job.runs += 1;
+                        (job.name.clone(), job.command.clone())
+                    })
+                    .collect::<Vec<_>>()
+            };
+            for (name, command) in due {
+                let commands = commands.clone();
+                tokio::spawn(async move { run_command(&commands, &name, command).await });
+            }
+        }
+    });
+}
@@ -0,0 +1,240 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use mcprotocol::msg;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+
+use crate::chat::ChatHandlerPacket;
+use crate::config::ShutdownConfig;
+use crate::db::SavePacket;
+
+struct ShutdownState {
+    stopping: AtomicBool,
+    active_sessions: AtomicUsize,
+}
+
+struct ShutdownRequest {
+    reason: String,
+    // Signals come from orchestrators with a short grace period (Docker gives 10s before
+    // SIGKILL), so they get their own countdown and a repeat skips it.
+    signal: bool,
+}
+
+#[derive(Clone)]
+pub struct ShutdownTrigger {
+    requests: UnboundedSender<ShutdownRequest>,
+}
+
+impl ShutdownTrigger {
+    pub fn request<S: Into<String>>(&self, reason: S) {
+        let _ = self.requests.send(ShutdownRequest {
+            reason: reason.into(),
+            signal: false,
+        });
+    }
+
+    pub fn signal<S: Into<String>>(&self, reason: S) {
+        let _ = self.requests.send(ShutdownRequest {
+            reason: reason.into(),
+            signal: true,
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct ShutdownListener {
+    state: Arc<ShutdownState>,
+    kick: watch::Receiver<Option<String>>,
+}
+
+impl ShutdownListener {
+    pub fn is_stopping(&self) -> bool {
+        self.state.stopping.load(Ordering::SeqCst)
+    }
+
+    pub fn kick_reason(&self) -> Option<String> {
+        self.kick.borrow().clone()
+    }
+
+    pub fn register_session(&self) -> SessionGuard {
+        self.state.active_sessions.fetch_add(1, Ordering::SeqCst);
+        SessionGuard {
+            state: self.state.clone(),
+        }
+    }
+}
+
+// Held by every game session task until its final save has completed.
+pub struct SessionGuard {
+    state: Arc<ShutdownState>,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.state.active_sessions.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct ShutdownCoordinator {
+    state: Arc<ShutdownState>,
+    requests: UnboundedReceiver<ShutdownRequest>,
+    kick: watch::Sender<Option<String>>,
+}
+
+pub fn create_shutdown_handles() -> (ShutdownTrigger, ShutdownListener, ShutdownCoordinator) {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let (kick_tx, kick_rx) = watch::channel(None);
+    let state = Arc::new(ShutdownState {
+        stopping: AtomicBool::new(false),
+        active_sessions: AtomicUsize::new(0),
+    });
+    (
+        ShutdownTrigger { requests: tx },
+        ShutdownListener {
+            state: state.clone(),
+            kick: kick_rx,
+        },
+        ShutdownCoordinator {
+            state,
+            requests: rx,
+            kick: kick_tx,
+        },
+    )
+}
+
+fn countdown_marks(countdown: u64) -> Vec<u64> {
+    (1..=countdown)
+        .rev()
+        .filter(|second| *second == countdown || *second <= 5 || *second % 10 == 0)
+        .collect()
+}
+
+async fn countdown(chat: &UnboundedSender<ChatHandlerPacket>, countdown: u64) {
+    let mut remaining = countdown;
+    for mark in countdown_marks(countdown) {
+        tokio::time::sleep(Duration::from_secs(remaining - mark)).await;
+        remaining = mark;
+        let _ = chat.send(ChatHandlerPacket::BroadcastMessage(
+            msg!(format!("Server stopping in {} second(s)...", mark), "red")
+                .bold(true)
+                .into(),
+        ));
+    }
+    tokio::time::sleep(Duration::from_secs(remaining)).await;
+}
+
+// Resolves on the next signal; other requests are already covered by the running shutdown.
+async fn next_signal(requests: &mut UnboundedReceiver<ShutdownRequest>) {
+    loop {
+        match requests.recv().await {
+            Some(request) if request.signal => return,
+            Some(_) => continue,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl ShutdownCoordinator {
+    pub fn spawn(
+        self,
+        chat: UnboundedSender<ChatHandlerPacket>,
+        saves: UnboundedSender<SavePacket>,
+        config: ShutdownConfig,
+    ) {
+        tokio::spawn(async move { self.run(chat, saves, config).await });
+    }
+
+    async fn run(
+        mut self,
+        chat: UnboundedSender<ChatHandlerPacket>,
+        saves: UnboundedSender<SavePacket>,
+        config: ShutdownConfig,
+    ) {
+        let request = match self.requests.recv().await {
+            Some(request) => request,
+            None => return,
+        };
+        log::info!("Shutdown requested: {}", request.reason);
+        self.state.stopping.store(true, Ordering::SeqCst);
+
+        let countdown_secs = if request.signal {
+            config.signal_countdown_secs
+        } else {
+            config.countdown_secs
+        };
+        tokio::select! {
+            _ = countdown(&chat, countdown_secs) => {}
+            _ = next_signal(&mut self.requests) => {
+                log::info!("Received another signal, skipping the countdown.");
+            }
+        }
+
+        log::info!("Disconnecting players and saving sessions.");
+        let _ = self.kick.send(Some(config.kick_message.clone()));
+
+        let started = Instant::now();
+        let timeout = Duration::from_secs(config.save_timeout_secs);
+        loop {
+            let active = self.state.active_sessions.load(Ordering::SeqCst);
+            if active == 0 {
+                log::info!("All sessions stopped.");
+                break;
+            }
+            if started.elapsed() > timeout {
+                log::error!(
+                    "Timed out waiting for {} session(s) to stop; saving what was queued.",
+                    active
+                );
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        // Still give the worker a moment if the sessions used up the whole budget.
+        let remaining = timeout
+            .saturating_sub(started.elapsed())
+            .max(Duration::from_secs(5));
+        match tokio::time::timeout(remaining, crate::db::flush(&saves)).await {
+            Ok(true) => log::info!("All player data written."),
+            Ok(false) => log::error!("Save worker stopped before flushing player data."),
+            Err(_) => log::error!("Timed out waiting for queued saves to be written."),
+        }
+
+        log::info!("Shutdown complete.");
+        log::logger().flush();
+        crate::console::restore_terminal();
+        std::process::exit(0);
+    }
+}
+
+pub fn listen_for_signals(trigger: ShutdownTrigger) {
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut terminate = match signal(SignalKind::terminate()) {
+                Ok(terminate) => terminate,
+                Err(err) => {
+                    log::error!("Failed to register SIGTERM handler: {}", err);
+                    return;
+                }
+            };
+            loop {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => trigger.signal("Received SIGINT"),
+                    _ = terminate.recv() => trigger.signal("Received SIGTERM"),
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        loop {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            trigger.signal("Received Ctrl-C");
+        }
+    });
+}